@@ -0,0 +1,360 @@
+use super::storage::TileMap;
+use super::Maze;
+use derive_more::Display;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+/// The algorithm used to carve the passages of a maze. Every algorithm creates a perfect maze, so
+/// there is exactly one path between any two open tiles, but the mazes look different.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Generator {
+    /// Randomised Kruskal's algorithm, giving many short dead ends.
+    #[default]
+    Kruskal,
+    /// A randomised depth first search, giving long winding corridors.
+    Backtracker,
+}
+
+/// How much of the maze the player can see.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Visibility {
+    /// Only the tiles the player has been next to are revealed.
+    #[default]
+    Fog,
+    /// The whole maze is revealed from the start.
+    Revealed,
+}
+
+/// Everything needed to generate a maze. The same configuration always generates the same maze.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq)]
+pub struct MazeConfig {
+    pub width: usize,
+    pub height: usize,
+    pub seed: u64,
+    #[serde(default)]
+    pub generator: Generator,
+    #[serde(default)]
+    pub visibility: Visibility,
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Display, PartialEq)]
+#[display(fmt = "the width and height of a maze must be odd")]
+pub struct InvalidSize;
+
+impl MazeConfig {
+    /// Generates the maze described by this configuration.
+    pub fn generate(&self) -> Result<Maze, InvalidSize> {
+        self.generate_with(false)
+    }
+
+    /// Generates the same maze as `generate`, storing its tiles as bitsets like
+    /// `Maze::new_compact`.
+    pub fn generate_compact(&self) -> Result<Maze, InvalidSize> {
+        self.generate_with(true)
+    }
+
+    fn generate_with(&self, compact: bool) -> Result<Maze, InvalidSize> {
+        if self.width & 1 == 0 || self.height & 1 == 0 {
+            return Err(InvalidSize);
+        }
+
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let map = self
+            .generator
+            .generate(self.width, self.height, compact, &mut rng);
+        Ok(Maze::with_map(
+            self.width,
+            self.height,
+            map,
+            self.visibility,
+        ))
+    }
+}
+
+impl Generator {
+    /// Generates the tiles of a `width` by `height` maze, both of which must be odd. Open cells sit
+    /// on even coordinates and the walls between them are opened up by the algorithm. The tiles
+    /// are written straight into the map, so generating a compact maze never needs a `Tile` per
+    /// cell.
+    pub(crate) fn generate(
+        self,
+        width: usize,
+        height: usize,
+        compact: bool,
+        rng: &mut impl Rng,
+    ) -> TileMap {
+        assert!(
+            width & 1 == 1 && height & 1 == 1,
+            "Random maze only allows odd numbers"
+        );
+        assert!(
+            width
+                .checked_mul(height)
+                .is_some_and(|tiles| tiles <= u32::MAX as usize),
+            "Random maze only allows up to u32::MAX tiles"
+        );
+
+        let mut map = TileMap::blocked(width * height, compact);
+        match self {
+            Generator::Kruskal => kruskal(width, height, &mut map, rng),
+            Generator::Backtracker => backtracker(width, height, &mut map, rng),
+        }
+        map
+    }
+}
+
+/// Visits the walls between cells in a random order and opens each one that separates two cells
+/// that are not connected yet. Besides the map, only the walls and a disjoint set of the cells are
+/// kept, both as `u32` indices.
+fn kruskal(width: usize, height: usize, map: &mut TileMap, rng: &mut impl Rng) {
+    let cells_wide = width.div_ceil(2);
+    let cell = |x: usize, y: usize| (cells_wide * (y / 2) + x / 2) as u32;
+    // The parent of each cell in the disjoint set, where a cell that is its own parent is the root
+    // of its set.
+    let mut parents: Vec<u32> = (0..cells_wide * height.div_ceil(2))
+        .map(|cell| cell as u32)
+        .collect();
+
+    for y in (0..height).step_by(2) {
+        for x in (0..width).step_by(2) {
+            map.open(width * y + x);
+        }
+    }
+
+    // The walls sit between two cells, with one odd and one even coordinate, which is every other
+    // tile.
+    let mut walls = Vec::with_capacity(width * height / 2);
+    walls.extend(
+        (0..width * height)
+            .filter(|&i| (i % width + i / width) & 1 == 1)
+            .map(|i| i as u32),
+    );
+    walls.shuffle(rng);
+
+    for wall in walls {
+        let (x, y) = (wall as usize % width, wall as usize / width);
+        let (p, q) = if y & 1 == 0 {
+            (cell(x + 1, y), cell(x - 1, y))
+        } else {
+            (cell(x, y - 1), cell(x, y + 1))
+        };
+        let (p, q) = (find(&mut parents, p), find(&mut parents, q));
+
+        if p != q {
+            map.open(wall as usize);
+            parents[p as usize] = q;
+        }
+    }
+}
+
+/// The root of the set of `cell`. Halves the path to the root on the way so later lookups are
+/// quicker, and is iterative rather than recursive so that the long chains of very large mazes
+/// cannot overflow the stack.
+fn find(parents: &mut [u32], mut cell: u32) -> u32 {
+    while parents[cell as usize] != cell {
+        let grandparent = parents[parents[cell as usize] as usize];
+        parents[cell as usize] = grandparent;
+        cell = grandparent;
+    }
+    cell
+}
+
+/// Walks from the start to a random unvisited neighbouring cell, opening the wall in between, and
+/// backtracks whenever it gets stuck until every cell has been visited. Cells are unvisited for as
+/// long as they are blocked in the map, so only the path back is kept besides it.
+fn backtracker(width: usize, height: usize, map: &mut TileMap, rng: &mut impl Rng) {
+    let mut stack = vec![0u32];
+    map.open(0);
+
+    while let Some(&i) = stack.last() {
+        let (x, y) = (i as usize % width, i as usize / width);
+        let unvisited = [(2, 0), (-2, 0), (0, 2), (0, -2)]
+            .iter()
+            .map(|&(dx, dy)| (x as isize + dx, y as isize + dy))
+            .filter(|&(nx, ny)| {
+                nx >= 0
+                    && ny >= 0
+                    && (nx as usize) < width
+                    && (ny as usize) < height
+                    && map.is_blocked(width * ny as usize + nx as usize)
+            })
+            .map(|(nx, ny)| (nx as usize, ny as usize))
+            .collect::<Vec<_>>();
+
+        match unvisited.choose(rng) {
+            Some(&(nx, ny)) => {
+                map.open(width * ((y + ny) / 2) + (x + nx) / 2);
+                map.open(width * ny + nx);
+                stack.push((width * ny + nx) as u32);
+            }
+            None => {
+                stack.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    /// Passes allocations on to the system allocator, keeping count of how many bytes the current
+    /// thread has allocated and the most it had allocated at once.
+    struct CountingAllocator;
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    thread_local! {
+        static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+        static PEAK: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATED.try_with(|allocated| {
+                allocated.set(allocated.get() + layout.size());
+                let _ = PEAK.try_with(|peak| peak.set(peak.get().max(allocated.get())));
+            });
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let _ = ALLOCATED
+                .try_with(|allocated| allocated.set(allocated.get().saturating_sub(layout.size())));
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    /// The most bytes allocated at once by `generate`, on top of what was allocated before.
+    fn peak_bytes(generate: impl FnOnce() -> Maze) -> usize {
+        let before = ALLOCATED.with(Cell::get);
+        PEAK.with(|peak| peak.set(before));
+        let maze = generate();
+        let peak = PEAK.with(Cell::get) - before;
+        drop(maze);
+        peak
+    }
+
+    #[test]
+    /// The same configuration always generates the same maze, for every generator.
+    fn same_config_same_maze() {
+        for &generator in &[Generator::Kruskal, Generator::Backtracker] {
+            let config = MazeConfig {
+                width: 21,
+                height: 11,
+                seed: 7,
+                generator,
+                visibility: Visibility::Fog,
+            };
+            let a = config.generate().unwrap();
+            let b = config.generate().unwrap();
+
+            assert_eq!(a.tiles(), b.tiles());
+            assert_eq!((a.width(), a.height()), (21, 11));
+        }
+    }
+
+    #[test]
+    /// A compact maze generated from a configuration is the same maze as a regular one.
+    fn compact_config_same_maze() {
+        let config = MazeConfig {
+            width: 15,
+            height: 9,
+            seed: 3,
+            generator: Generator::Backtracker,
+            visibility: Visibility::Revealed,
+        };
+        let dense = config.generate().unwrap();
+        let compact = config.generate_compact().unwrap();
+
+        assert!(compact.is_compact());
+        assert_eq!(compact.tiles(), dense.tiles());
+    }
+
+    #[test]
+    /// Generating a compact maze never holds a struct for every cell, only a few bytes per tile at
+    /// its peak, which is less than generating a dense maze takes, for every generator.
+    fn compact_generation_stays_small() {
+        for &generator in &[Generator::Kruskal, Generator::Backtracker] {
+            let config = MazeConfig {
+                width: 1001,
+                height: 1001,
+                seed: 5,
+                generator,
+                visibility: Visibility::Fog,
+            };
+            let tiles = config.width * config.height;
+
+            let compact = peak_bytes(|| config.generate_compact().unwrap());
+            let dense = peak_bytes(|| config.generate().unwrap());
+
+            assert!(compact < 4 * tiles, "{:?}: {} bytes", generator, compact);
+            assert!(compact < dense, "{:?}: {} >= {}", generator, compact, dense);
+        }
+    }
+
+    #[test]
+    /// Every generator produces perfect mazes of any odd size.
+    fn generators_make_perfect_mazes() {
+        for seed in 0..200 {
+            for &generator in &[Generator::Kruskal, Generator::Backtracker] {
+                let config = MazeConfig {
+                    width: 1 + 2 * (seed as usize % 7),
+                    height: 1 + 2 * (seed as usize % 5),
+                    seed,
+                    generator,
+                    visibility: Visibility::Fog,
+                };
+
+                let report = config.generate().unwrap().validate();
+                assert!(report.is_perfect(), "{:?}: {:?}", config, report);
+            }
+        }
+    }
+
+    #[test]
+    /// A revealed maze shows every tile from the start and does not count them as revealed by the
+    /// player.
+    fn revealed_maze_shows_all_tiles() {
+        let maze = MazeConfig {
+            width: 9,
+            height: 7,
+            seed: 1,
+            generator: Generator::Kruskal,
+            visibility: Visibility::Revealed,
+        }
+        .generate()
+        .unwrap();
+
+        assert!(maze.tiles().iter().all(|&tile| tile != 0));
+        assert_eq!(maze.stats().tiles_revealed, 0);
+    }
+
+    #[test]
+    /// Mazes must have an odd width and height.
+    fn even_sizes_rejected() {
+        for &(width, height) in &[(0, 9), (9, 0), (8, 9), (9, 10)] {
+            let config = MazeConfig {
+                width,
+                height,
+                seed: 0,
+                generator: Generator::Kruskal,
+                visibility: Visibility::Fog,
+            };
+            assert_eq!(config.generate().unwrap_err(), InvalidSize);
+        }
+    }
+}
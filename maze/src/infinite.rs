@@ -0,0 +1,649 @@
+use super::movement::step_allowed;
+use super::storage::TileMap;
+use super::{
+    CornerCutting, Direction, DirectionBlocked, GameStats, GameStatus, Generator, LocalView,
+    MapTile, Movement, MovesApplied, NeighbouringTileTypes, Probe, RevealedTile, ScoreInputs, Tile,
+    TileType, MAX_VIEW_RADIUS,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::ser::{SerializeSeq, Serializer};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+/// The number of tiles along each side of a chunk. Must be even so that every chunk starts on an
+/// open cell and ends on the row and column of passages it shares with its neighbours.
+const CHUNK_SIZE: i64 = 16;
+
+/// How far `look` follows a corridor before giving up. Corridors can cross chunk borders, so
+/// without a limit a single probe could generate an unbounded number of chunks.
+const MAX_PROBE_DISTANCE: usize = 4 * CHUNK_SIZE as usize;
+
+/// The farthest the exit can be placed from the start. Positions are signed 64 bit numbers, so this
+/// keeps every coordinate on the way to the exit far from overflowing.
+pub const MAX_EXIT_DISTANCE: u64 = 1 << 40;
+
+/// A maze without borders. The world is split into square chunks, each generated from the seed and
+/// its own coordinates the first time the player can see into it, so only the explored part of the
+/// maze is ever held in memory.
+///
+/// Every chunk is a perfect maze on its own and opens exactly one passage into its right and its
+/// bottom neighbour, which keeps the whole world connected no matter which chunks exist yet.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Clone)]
+pub struct InfiniteMaze {
+    seed: u64,
+    player: WorldPosition,
+    exit: WorldPosition,
+    chunks: HashMap<ChunkPosition, TileMap>,
+    movement: Movement,
+    corner_cutting: CornerCutting,
+    probe_cost: usize,
+    probes: usize,
+    stats: GameStats,
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct WorldPosition {
+    pub x: i64,
+    pub y: i64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct ChunkPosition {
+    x: i64,
+    y: i64,
+}
+
+/// A square view of an `InfiniteMaze` centred on the player. Serializes to the same 2d array as the
+/// map of a `Maze`.
+pub struct MapWindow<'a> {
+    maze: &'a InfiniteMaze,
+    radius: i64,
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+impl InfiniteMaze {
+    /// Creates a new unbounded maze from `seed`, with the exit roughly `exit_distance` steps (as
+    /// the crow walks on the grid) away from the player. Distances beyond `MAX_EXIT_DISTANCE` are
+    /// lowered to it.
+    pub fn new(seed: u64, exit_distance: u64) -> Self {
+        let mut maze = InfiniteMaze {
+            seed,
+            player: WorldPosition { x: 0, y: 0 },
+            exit: InfiniteMaze::exit_position(seed, exit_distance.min(MAX_EXIT_DISTANCE)),
+            chunks: HashMap::new(),
+            movement: Movement::default(),
+            corner_cutting: CornerCutting::default(),
+            probe_cost: 0,
+            probes: 0,
+            stats: GameStats::default(),
+        };
+
+        maze.reveal_around_player();
+        maze
+    }
+
+    fn exit_position(seed: u64, exit_distance: u64) -> WorldPosition {
+        // Open cells sit on even coordinates, so pick an even point on the diamond of the requested
+        // Manhattan distance.
+        let distance = (exit_distance + exit_distance % 2) as i64;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let x = rng.gen_range(-distance / 2, distance / 2 + 1) * 2;
+        let y = distance - x.abs();
+
+        WorldPosition {
+            x,
+            y: if rng.gen() { y } else { -y },
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn player(&self) -> WorldPosition {
+        self.player
+    }
+
+    pub fn exit(&self) -> WorldPosition {
+        self.exit
+    }
+
+    pub fn status(&self) -> GameStatus {
+        if self.player == self.exit {
+            GameStatus::Won
+        } else {
+            GameStatus::Playing
+        }
+    }
+
+    /// What the player has done so far, for scoring the game.
+    pub fn stats(&self) -> GameStats {
+        self.stats
+    }
+
+    /// The number of chunks that have been generated so far.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn move_player(&mut self, direction: Direction) -> Result<(), JsValue> {
+        self.internal_move_player(direction)
+            .map_err(|_| JsValue::from_str("direction blocked"))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn move_player(&mut self, direction: Direction) -> Result<(), DirectionBlocked> {
+        self.internal_move_player(direction)
+    }
+
+    fn internal_move_player(&mut self, direction: Direction) -> Result<(), DirectionBlocked> {
+        self.step(direction).map(|_| ())
+    }
+
+    pub fn neighbouring_tile_types(&self) -> NeighbouringTileTypes {
+        let WorldPosition { x, y } = self.player;
+
+        NeighbouringTileTypes::around(self.movement, self.corner_cutting, |dx, dy| {
+            self.tile_type_at(x + i64::from(dx), y + i64::from(dy))
+        })
+    }
+
+    /// Switches between orthogonal and diagonal movement. Diagonal movement also reveals and
+    /// reports the diagonal neighbours of the player.
+    pub fn set_movement(&mut self, movement: Movement) {
+        self.movement = movement;
+        self.reveal_around_player();
+    }
+
+    /// Sets whether diagonal steps may pass between blocked tiles.
+    pub fn set_corner_cutting(&mut self, corner_cutting: CornerCutting) {
+        self.corner_cutting = corner_cutting;
+    }
+
+    pub fn movement(&self) -> Movement {
+        self.movement
+    }
+
+    pub fn corner_cutting(&self) -> CornerCutting {
+        self.corner_cutting
+    }
+
+    /// Looks along each direction the player can move in, revealing every tile up to and including
+    /// the next wall. Returns how many open tiles lie before each of the walls, counting at most
+    /// `MAX_PROBE_DISTANCE` tiles.
+    pub fn look(&mut self) -> Probe {
+        self.probes += 1;
+
+        let WorldPosition { x, y } = self.player;
+        Probe::new(self.movement, self.probe_cost, |direction| {
+            let (dx, dy) = direction.offset();
+            let (dx, dy) = (i64::from(dx), i64::from(dy));
+            let (mut x, mut y) = (x, y);
+            let mut distance = 0;
+
+            while distance < MAX_PROBE_DISTANCE
+                && step_allowed(self.movement, self.corner_cutting, direction, |sx, sy| {
+                    self.tile_type_at(x + i64::from(sx), y + i64::from(sy))
+                })
+            {
+                x += dx;
+                y += dy;
+                self.reveal(x, y);
+                distance += 1;
+            }
+
+            if distance < MAX_PROBE_DISTANCE {
+                self.reveal(x + dx, y + dy);
+            }
+            distance
+        })
+    }
+
+    /// Sets how much each call to `look` costs. Probing is free unless a cost is set.
+    pub fn set_probe_cost(&mut self, cost: usize) {
+        self.probe_cost = cost;
+    }
+
+    pub fn probe_cost(&self) -> usize {
+        self.probe_cost
+    }
+
+    /// How many times the player has probed with `look`.
+    pub fn probes(&self) -> usize {
+        self.probes
+    }
+
+    /// The `(2 * radius + 1)` tiles wide square around the player, with the radius lowered to
+    /// `MAX_VIEW_RADIUS` if it is larger.
+    pub fn local_view(&self, radius: usize) -> LocalView {
+        let WorldPosition { x, y } = self.player;
+        let radius = radius.min(MAX_VIEW_RADIUS) as i64;
+
+        LocalView::new(
+            (y - radius..=y + radius)
+                .flat_map(|y| (x - radius..=x + radius).map(move |x| (x, y)))
+                .map(|(x, y)| self.map_tile_at(x, y))
+                .collect(),
+        )
+    }
+
+    /// Reveals the tiles around the player and returns the positions of those that were hidden.
+    fn reveal_around_player(&mut self) -> Vec<WorldPosition> {
+        let player = self.player;
+        let mut revealed = Vec::new();
+
+        if self.reveal(player.x, player.y) {
+            revealed.push(player);
+        }
+        for direction in self.movement.directions() {
+            let (dx, dy) = direction.offset();
+            let (x, y) = (player.x + i64::from(dx), player.y + i64::from(dy));
+            if self.reveal(x, y) {
+                revealed.push(WorldPosition { x, y });
+            }
+        }
+        revealed
+    }
+}
+
+impl InfiniteMaze {
+    /// Returns a view of the `(2 * radius + 1)` tiles wide square around the player.
+    pub fn window(&self, radius: usize) -> MapWindow<'_> {
+        MapWindow {
+            maze: self,
+            radius: radius as i64,
+        }
+    }
+
+    /// A lower bound for the fewest moves from the start to the exit: the distance on an open grid
+    /// under the current movement rules. Finding the real shortest path could need arbitrarily many
+    /// chunks to be generated.
+    pub fn optimal_moves(&self) -> usize {
+        let (x, y) = (self.exit.x.unsigned_abs(), self.exit.y.unsigned_abs());
+        let distance = match self.movement {
+            Movement::Orthogonal => x + y,
+            Movement::Diagonal => x.max(y),
+        };
+        distance as usize
+    }
+
+    /// Gathers the inputs for scoring the game so far, given how long it has been played for.
+    pub fn score_inputs(&self, elapsed: Duration) -> ScoreInputs {
+        ScoreInputs {
+            moves: self.stats.moves,
+            optimal_moves: self.optimal_moves(),
+            blocked_moves: self.stats.blocked_moves,
+            tiles_revealed: self.stats.tiles_revealed,
+            probe_cost: self.probes.saturating_mul(self.probe_cost),
+            elapsed,
+        }
+    }
+
+    /// Takes each of the moves in `directions` in turn, stopping at the first one that is blocked.
+    /// Returns how far the player got and every tile revealed on the way.
+    pub fn apply_moves(&mut self, directions: &[Direction]) -> MovesApplied<WorldPosition> {
+        let mut applied = MovesApplied {
+            moved: 0,
+            blocked: false,
+            revealed: Vec::new(),
+        };
+
+        for &direction in directions {
+            match self.step(direction) {
+                Ok(revealed) => {
+                    applied.moved += 1;
+                    applied
+                        .revealed
+                        .extend(revealed.into_iter().map(|position| {
+                            // Revealing a tile always generates its chunk.
+                            let (chunk, i) = split(position.x, position.y);
+                            RevealedTile {
+                                position,
+                                tile: self.chunks[&chunk].get(i).into(),
+                            }
+                        }));
+                }
+                Err(DirectionBlocked) => {
+                    applied.blocked = true;
+                    break;
+                }
+            }
+        }
+
+        applied
+    }
+
+    /// Moves the player one tile in `direction` and returns the positions of the tiles that were
+    /// revealed by the move.
+    fn step(&mut self, direction: Direction) -> Result<Vec<WorldPosition>, DirectionBlocked> {
+        let WorldPosition { x, y } = self.player;
+
+        if !step_allowed(self.movement, self.corner_cutting, direction, |dx, dy| {
+            self.tile_type_at(x + i64::from(dx), y + i64::from(dy))
+        }) {
+            self.stats.blocked_moves += 1;
+            return Err(DirectionBlocked);
+        }
+
+        let (dx, dy) = direction.offset();
+        self.player = WorldPosition {
+            x: x + i64::from(dx),
+            y: y + i64::from(dy),
+        };
+        self.stats.moves += 1;
+
+        Ok(self.reveal_around_player())
+    }
+
+    /// Reveals the tile at the given coordinates, generating its chunk if needed. Returns whether
+    /// the tile was hidden before.
+    fn reveal(&mut self, x: i64, y: i64) -> bool {
+        let (chunk, i) = split(x, y);
+        let seed = self.seed;
+        let hidden = self
+            .chunks
+            .entry(chunk)
+            .or_insert_with(|| generate_chunk(seed, chunk))
+            .reveal(i);
+        if hidden {
+            self.stats.tiles_revealed += 1;
+        }
+        hidden
+    }
+
+    /// The tile at the given coordinates as seen by the player. Tiles of chunks that have not been
+    /// generated yet are hidden.
+    fn map_tile_at(&self, x: i64, y: i64) -> MapTile {
+        let position = WorldPosition { x, y };
+        if self.player == position {
+            MapTile::Player
+        } else if self.exit == position {
+            MapTile::Exit
+        } else {
+            let (chunk, i) = split(x, y);
+            self.chunks
+                .get(&chunk)
+                .map_or(MapTile::Hidden, |tiles| tiles.get(i).into())
+        }
+    }
+
+    fn tile_type_at(&self, x: i64, y: i64) -> TileType {
+        let (chunk, i) = split(x, y);
+        match self.chunks.get(&chunk) {
+            Some(tiles) => tiles.get(i).tile_type,
+            // Chunks are deterministic, so one that has not been stored yet can be regenerated.
+            None => generate_chunk(self.seed, chunk).get(i).tile_type,
+        }
+    }
+}
+
+/// Splits world coordinates into the chunk they belong to and the index of the tile in that chunk.
+fn split(x: i64, y: i64) -> (ChunkPosition, usize) {
+    let chunk = ChunkPosition {
+        x: x.div_euclid(CHUNK_SIZE),
+        y: y.div_euclid(CHUNK_SIZE),
+    };
+    let i = CHUNK_SIZE * y.rem_euclid(CHUNK_SIZE) + x.rem_euclid(CHUNK_SIZE);
+    (chunk, i as usize)
+}
+
+/// Mixes the seed with the chunk coordinates so that neighbouring chunks get unrelated generators.
+fn chunk_seed(seed: u64, chunk: ChunkPosition) -> u64 {
+    // splitmix64 finaliser applied to each input in turn.
+    fn mix(mut z: u64) -> u64 {
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    let z = mix(seed.wrapping_add(0x9e37_79b9_7f4a_7c15));
+    let z = mix(z ^ chunk.x as u64);
+    mix(z ^ (chunk.y as u64).rotate_left(32))
+}
+
+fn generate_chunk(seed: u64, chunk: ChunkPosition) -> TileMap {
+    let size = CHUNK_SIZE as usize;
+    let mut rng = StdRng::seed_from_u64(chunk_seed(seed, chunk));
+
+    // The chunk is a regular odd sized maze plus the last row and column, which hold the passages
+    // into the neighbouring chunks.
+    let inner = Generator::Kruskal.generate(size - 1, size - 1, false, &mut rng);
+    // Sampled as u32 rather than usize, which rand draws differently on 32 and 64 bit targets, so
+    // that the browser builds the same world as the server.
+    let right_passage = rng.gen_range(0, size as u32 / 2) as usize * 2;
+    let bottom_passage = rng.gen_range(0, size as u32 / 2) as usize * 2;
+
+    TileMap::compact((0..size * size).map(|i| {
+        let (x, y) = (i % size, i / size);
+        if x < size - 1 && y < size - 1 {
+            inner.get((size - 1) * y + x)
+        } else if (x == size - 1 && y == right_passage) || (y == size - 1 && x == bottom_passage) {
+            Tile::open()
+        } else {
+            Tile::blocked()
+        }
+    }))
+}
+
+impl<'a> Serialize for MapWindow<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        struct Row<'a> {
+            y: i64,
+            window: &'a MapWindow<'a>,
+        }
+
+        impl<'a> Serialize for Row<'a> {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                let maze = self.window.maze;
+                let radius = self.window.radius;
+                let mut seq = serializer.serialize_seq(Some(2 * radius as usize + 1))?;
+                for x in maze.player.x - radius..=maze.player.x + radius {
+                    seq.serialize_element(&maze.map_tile_at(x, self.y))?;
+                }
+                seq.end()
+            }
+        }
+
+        let player = self.maze.player;
+        let mut seq = serializer.serialize_seq(Some(2 * self.radius as usize + 1))?;
+        for y in player.y - self.radius..=player.y + self.radius {
+            seq.serialize_element(&Row { y, window: self })?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashSet, VecDeque};
+
+    #[test]
+    /// Chunks are only generated once the player can see into them.
+    fn chunks_are_generated_lazily() {
+        let maze = InfiniteMaze::new(7, 1000);
+
+        // The start sits in the corner of a chunk, so the left and upper neighbours are revealed.
+        assert_eq!(maze.chunk_count(), 3);
+    }
+
+    #[test]
+    /// The optimal moves of an infinite maze are the grid distance to the exit.
+    fn optimal_moves_is_grid_distance() {
+        let mut maze = InfiniteMaze::new(3, 40);
+        let WorldPosition { x, y } = maze.exit();
+        assert_eq!(maze.optimal_moves() as i64, x.abs() + y.abs());
+
+        maze.set_movement(Movement::Diagonal);
+        assert_eq!(maze.optimal_moves() as i64, x.abs().max(y.abs()));
+    }
+
+    #[test]
+    /// The same seed always produces the same world.
+    fn same_seed_same_world() {
+        for &(x, y) in &[(0, 0), (-1, 5), (17, -33), (-100, -100)] {
+            let chunk = split(x, y).0;
+            let a = generate_chunk(42, chunk);
+            let b = generate_chunk(42, chunk);
+            for i in 0..(CHUNK_SIZE * CHUNK_SIZE) as usize {
+                assert_eq!(a.get(i), b.get(i));
+            }
+        }
+    }
+
+    #[test]
+    /// The exit is an open tile at the requested distance from the start.
+    fn exit_is_open_at_distance() {
+        for seed in 0..50 {
+            let maze = InfiniteMaze::new(seed, 40);
+            let exit = maze.exit();
+
+            assert_eq!(exit.x.abs() + exit.y.abs(), 40);
+            assert_eq!(maze.tile_type_at(exit.x, exit.y), TileType::Open);
+        }
+    }
+
+    #[test]
+    /// Exits farther than the largest distance are placed at that distance instead.
+    fn exit_distance_capped() {
+        let exit = InfiniteMaze::new(1, u64::MAX).exit();
+
+        assert_eq!((exit.x.abs() + exit.y.abs()) as u64, MAX_EXIT_DISTANCE);
+    }
+
+    #[test]
+    /// The exit can be reached from the start by walking across chunk borders.
+    fn exit_is_reachable_across_chunks() {
+        for seed in 0..10 {
+            let mut maze = InfiniteMaze::new(seed, 60);
+            let limit = 60 + 2 * CHUNK_SIZE;
+
+            let chunks = limit / CHUNK_SIZE + 1;
+            for x in -chunks..=chunks {
+                for y in -chunks..=chunks {
+                    let chunk = ChunkPosition { x, y };
+                    maze.chunks.insert(chunk, generate_chunk(seed, chunk));
+                }
+            }
+
+            let mut seen = HashSet::new();
+            let mut queue = VecDeque::new();
+            queue.push_back(maze.player());
+            seen.insert(maze.player());
+
+            while let Some(WorldPosition { x, y }) = queue.pop_front() {
+                for &(x, y) in &[(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                    let position = WorldPosition { x, y };
+                    if x.abs() <= limit
+                        && y.abs() <= limit
+                        && !seen.contains(&position)
+                        && maze.tile_type_at(x, y) == TileType::Open
+                    {
+                        seen.insert(position);
+                        queue.push_back(position);
+                    }
+                }
+            }
+
+            assert!(
+                seen.contains(&maze.exit()),
+                "exit unreachable for seed {}",
+                seed
+            );
+        }
+    }
+
+    #[test]
+    /// The window is a square of tiles centred on the player.
+    fn window_serializes_around_player() {
+        let maze = InfiniteMaze::new(3, 100);
+        let window: Vec<Vec<String>> =
+            serde_json::from_str(&serde_json::to_string(&maze.window(2)).unwrap()).unwrap();
+
+        assert_eq!(window.len(), 5);
+        assert!(window.iter().all(|row| row.len() == 5));
+        assert_eq!(window[2][2], "player");
+        assert_eq!(window[0][0], "hidden");
+    }
+
+    #[test]
+    /// Applying moves reports the tiles revealed along the way as the map shows them.
+    fn apply_moves_reveals_tiles() {
+        let mut maze = InfiniteMaze::new(11, 100);
+        let directions = [
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+            Direction::Up,
+        ];
+        let applied = maze.apply_moves(&directions);
+
+        assert_eq!(applied.blocked, applied.moved < directions.len());
+        for revealed in applied.revealed {
+            let WorldPosition { x, y } = revealed.position;
+            if revealed.position != maze.player() {
+                assert_eq!(maze.map_tile_at(x, y), revealed.tile);
+            }
+        }
+    }
+
+    #[test]
+    /// Looking reveals the corridors around the player without moving.
+    fn look_reveals_without_moving() {
+        let mut maze = InfiniteMaze::new(13, 100);
+        let probe = maze.look();
+
+        assert_eq!(maze.player(), WorldPosition { x: 0, y: 0 });
+        for x in 1..=probe.right as i64 {
+            assert_eq!(maze.map_tile_at(x, 0), MapTile::Open);
+        }
+        assert_eq!(
+            maze.map_tile_at(probe.right as i64 + 1, 0),
+            MapTile::Blocked
+        );
+    }
+
+    #[test]
+    /// The local view shows the same tiles as the map window of the same radius.
+    fn local_view_matches_window() {
+        let mut maze = InfiniteMaze::new(5, 100);
+        for &direction in &[
+            Direction::Down,
+            Direction::Right,
+            Direction::Up,
+            Direction::Left,
+        ] {
+            let _ = maze.move_player(direction);
+        }
+
+        assert_eq!(
+            serde_json::to_string(&maze.local_view(3)).unwrap(),
+            serde_json::to_string(&maze.window(3)).unwrap()
+        );
+    }
+
+    #[test]
+    /// Radii beyond the largest view are lowered to it rather than overflowing.
+    fn local_view_radius_capped() {
+        let maze = InfiniteMaze::new(5, 100);
+
+        let view = maze.local_view(usize::MAX);
+
+        assert_eq!(view.size(), 2 * MAX_VIEW_RADIUS + 1);
+        assert_eq!(view.tile(MAX_VIEW_RADIUS, MAX_VIEW_RADIUS), MapTile::Player);
+    }
+}
@@ -1,9 +1,32 @@
 use derive_more::Display;
-use rand::seq::SliceRandom;
+use movement::step_allowed;
 use rand::thread_rng;
 use serde::ser::{SerializeSeq, Serializer};
 use serde::{self, Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use storage::TileMap;
+
+pub use generator::{Generator, InvalidSize, MazeConfig, Visibility};
+pub use infinite::{InfiniteMaze, MapWindow, WorldPosition, MAX_EXIT_DISTANCE};
+pub use movement::{CornerCutting, Movement};
+pub use replay::{Play, Replay, ReplayConfig, ReplayError, ReplayPlayer, Turn};
+pub use score::{
+    GameMode, GameStats, Score, ScoreInputs, ScoringFormula, SpeedScoring, StandardScoring,
+};
+pub use validate::{Problem, ValidationReport};
+
+mod generator;
+mod infinite;
+mod movement;
+mod replay;
+mod score;
+mod storage;
+mod validate;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
@@ -17,20 +40,24 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 #[display(fmt = "direction blocked")]
 pub struct DirectionBlocked;
 
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Display, PartialEq)]
+#[display(fmt = "unknown direction")]
+pub struct UnknownDirection;
+
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 #[derive(Debug, Clone)]
 pub struct Maze {
     player: Position,
     exit: Position,
-    size: usize,
-    map: Vec<Tile>,
-}
-
-#[derive(Debug, Clone)]
-struct MazeGenerationTile {
-    position: Position,
-    link: Position,
-    tile_type: Option<TileType>,
+    width: usize,
+    height: usize,
+    map: TileMap,
+    movement: Movement,
+    corner_cutting: CornerCutting,
+    probe_cost: usize,
+    probes: usize,
+    stats: GameStats,
 }
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
@@ -40,6 +67,53 @@ pub struct NeighbouringTileTypes {
     right: TileType,
     up: TileType,
     down: TileType,
+    #[serde(rename = "up-left", skip_serializing_if = "Option::is_none")]
+    up_left: Option<TileType>,
+    #[serde(rename = "up-right", skip_serializing_if = "Option::is_none")]
+    up_right: Option<TileType>,
+    #[serde(rename = "down-left", skip_serializing_if = "Option::is_none")]
+    down_left: Option<TileType>,
+    #[serde(rename = "down-right", skip_serializing_if = "Option::is_none")]
+    down_right: Option<TileType>,
+}
+
+/// How many open tiles lie in each direction from the player before the next wall, see
+/// `Maze::look`. Diagonals are only probed when diagonal movement is enabled.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct Probe {
+    pub left: usize,
+    pub right: usize,
+    pub up: usize,
+    pub down: usize,
+    #[serde(rename = "up-left", skip_serializing_if = "Option::is_none")]
+    pub up_left: Option<usize>,
+    #[serde(rename = "up-right", skip_serializing_if = "Option::is_none")]
+    pub up_right: Option<usize>,
+    #[serde(rename = "down-left", skip_serializing_if = "Option::is_none")]
+    pub down_left: Option<usize>,
+    #[serde(rename = "down-right", skip_serializing_if = "Option::is_none")]
+    pub down_right: Option<usize>,
+    /// What the probe cost, as set with `set_probe_cost`.
+    pub cost: usize,
+}
+
+/// The outcome of applying a sequence of moves, see `Maze::apply_moves`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MovesApplied<P> {
+    /// The number of moves taken before one was blocked.
+    pub moved: usize,
+    /// Whether a blocked move stopped the sequence early.
+    pub blocked: bool,
+    /// The tiles revealed by the moves, in the order they were revealed.
+    pub revealed: Vec<RevealedTile<P>>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RevealedTile<P> {
+    #[serde(flatten)]
+    pub position: P,
+    pub tile: MapTile,
 }
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
@@ -70,6 +144,41 @@ pub enum TileType {
     Open,
 }
 
+/// What a single tile of the map looks like to the player. `Maze::tiles` encodes each tile as the
+/// `u8` value of its variant.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum MapTile {
+    Hidden = 0,
+    Open = 1,
+    Blocked = 2,
+    Player = 3,
+    Exit = 4,
+}
+
+/// The largest radius of a `LocalView`. Larger radii are lowered to it, so a view cannot grow
+/// large enough to run out of memory.
+pub const MAX_VIEW_RADIUS: usize = 32;
+
+/// A square of tiles centred on the player, as returned by `Maze::local_view`. Tiles the player has
+/// not seen yet are hidden and tiles outside of the maze are blocked.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalView {
+    size: usize,
+    tiles: Vec<MapTile>,
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GameStatus {
+    Playing,
+    Won,
+}
+
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 #[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq)]
 pub enum Direction {
@@ -81,139 +190,110 @@ pub enum Direction {
     Left,
     #[serde(rename = "right")]
     Right,
+    #[serde(rename = "up-left")]
+    UpLeft,
+    #[serde(rename = "up-right")]
+    UpRight,
+    #[serde(rename = "down-left")]
+    DownLeft,
+    #[serde(rename = "down-right")]
+    DownRight,
 }
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 impl Maze {
     pub fn new(size: usize) -> Self {
-        let random_map = Maze::generate_random_map(size);
+        Maze::with_map(
+            size,
+            size,
+            Generator::Kruskal.generate(size, size, false, &mut thread_rng()),
+            Visibility::Fog,
+        )
+    }
+
+    /// Creates a new random maze that stores its tiles as bitsets rather than one `Tile` per cell.
+    /// Behaves exactly like a maze created with `Maze::new` but needs far less memory for large
+    /// sizes.
+    pub fn new_compact(size: usize) -> Self {
+        Maze::with_map(
+            size,
+            size,
+            Generator::Kruskal.generate(size, size, true, &mut thread_rng()),
+            Visibility::Fog,
+        )
+    }
 
+    pub(crate) fn with_map(
+        width: usize,
+        height: usize,
+        random_map: TileMap,
+        visibility: Visibility,
+    ) -> Self {
         let mut maze = Maze {
             player: Position { x: 0, y: 0 },
             exit: Position {
-                x: size - 1,
-                y: size - 1,
+                x: width - 1,
+                y: height - 1,
             },
-            size,
+            width,
+            height,
             map: random_map,
+            movement: Movement::default(),
+            corner_cutting: CornerCutting::default(),
+            probe_cost: 0,
+            probes: 0,
+            stats: GameStats::default(),
         };
 
         maze.reveal_around_player();
-        maze
-    }
-
-    fn generate_random_map(size: usize) -> Vec<Tile> {
-        fn find(
-            size: usize,
-            map: &[MazeGenerationTile],
-            p: Position,
-            q: Position,
-        ) -> (Position, Position) {
-            let cell_p = map[size * p.y + p.x].link;
-            let cell_q = map[size * q.y + q.x].link;
-
-            if p != cell_p || q != cell_q {
-                find(size, map, cell_p, cell_q)
-            } else {
-                (cell_p, cell_q)
+        if visibility == Visibility::Revealed {
+            for i in 0..maze.map.len() {
+                maze.map.reveal(i);
             }
+            // The player did not reveal any of these tiles themselves.
+            maze.stats = GameStats::default();
         }
-
-        assert_eq!(size % 2, 1, "Random maze only allows odd numbers");
-
-        let mut gen_map = Vec::with_capacity(size * size);
-
-        for i in 0..size {
-            for j in 0..size {
-                let pos = Position { x: j, y: i };
-                gen_map.push(MazeGenerationTile {
-                    position: pos,
-                    link: pos,
-                    tile_type: match (j & 1 == 0, i & 1 == 0) {
-                        (true, true) => Some(TileType::Open),
-                        (false, false) => Some(TileType::Blocked),
-                        (false, true) | (true, false) => None,
-                    },
-                });
-            }
-        }
-
-        let mut neither_map = gen_map
-            .iter()
-            .cloned()
-            .filter(|x| match x.tile_type {
-                None => true,
-                _ => false,
-            })
-            .collect::<Vec<_>>();
-
-        neither_map.shuffle(&mut thread_rng());
-
-        for i in neither_map {
-            let pos = i.position;
-
-            let (p, q) = find(
-                size,
-                &gen_map,
-                if pos.y & 1 == 0 {
-                    Position {
-                        x: pos.x + 1,
-                        y: pos.y,
-                    }
-                } else {
-                    Position {
-                        x: pos.x,
-                        y: pos.y - 1,
-                    }
-                },
-                if pos.y & 1 == 0 {
-                    Position {
-                        x: pos.x - 1,
-                        y: pos.y,
-                    }
-                } else {
-                    Position {
-                        x: pos.x,
-                        y: pos.y + 1,
-                    }
-                },
-            );
-
-            if p != q {
-                gen_map[size * pos.y + pos.x].tile_type = Some(TileType::Open);
-                gen_map[size * p.y + p.x].link = q;
-            } else {
-                gen_map[size * pos.y + pos.x].tile_type = Some(TileType::Blocked);
-            }
-        }
-
-        gen_map
-            .iter()
-            .map(|x| Tile {
-                tile_type: x.tile_type.unwrap(),
-                visibility: TileVisibility::Hidden,
-            })
-            .collect::<Vec<_>>()
+        maze
     }
 
     fn to_index(&self, x: usize, y: usize) -> usize {
-        self.size * y + x
+        self.width * y + x
     }
 
-    fn reveal(&mut self, x: usize, y: usize) {
+    /// Whether `x` and `y` lie within the maze.
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height
+    }
+
+    /// Reveals the tile at `x` and `y`, returning whether it was hidden before.
+    fn reveal(&mut self, x: usize, y: usize) -> bool {
         let i = self.to_index(x, y);
-        self.map[i].reveal();
+        let hidden = self.map.reveal(i);
+        if hidden {
+            self.stats.tiles_revealed += 1;
+        }
+        hidden
     }
 
     fn tile_at(&self, x: usize, y: usize) -> Tile {
-        self.map[self.to_index(x, y)]
+        self.map.get(self.to_index(x, y))
     }
 
-    fn tile_type_at(&self, x: i32, y: i32) -> TileType {
-        if x < 0 || y < 0 || x >= self.size as i32 || y >= self.size as i32 {
-            TileType::Blocked
+    fn map_tile_at(&self, x: usize, y: usize) -> MapTile {
+        if self.player.x == x && self.player.y == y {
+            MapTile::Player
+        } else if self.exit.x == x && self.exit.y == y {
+            MapTile::Exit
         } else {
+            self.tile_at(x, y).into()
+        }
+    }
+
+    fn tile_type_at(&self, x: i32, y: i32) -> TileType {
+        if self.contains(x, y) {
             self.tile_at(x as usize, y as usize).tile_type
+        } else {
+            TileType::Blocked
         }
     }
 
@@ -229,63 +309,322 @@ impl Maze {
     }
 
     fn internal_move_player(&mut self, direction: Direction) -> Result<(), DirectionBlocked> {
-        use Direction::*;
+        self.step(direction).map(|_| ())
+    }
 
-        let (x, y) = match direction {
-            Up => (self.player.x as i32, self.player.y as i32 - 1),
-            Down => (self.player.x as i32, self.player.y as i32 + 1),
-            Left => (self.player.x as i32 - 1, self.player.y as i32),
-            Right => (self.player.x as i32 + 1, self.player.y as i32),
-        };
+    /// Moves the player one tile in `direction` and returns the positions of the tiles that were
+    /// revealed by the move.
+    fn step(&mut self, direction: Direction) -> Result<Vec<Position>, DirectionBlocked> {
+        let player_x = self.player.x as i32;
+        let player_y = self.player.y as i32;
 
-        if x < 0
-            || y < 0
-            || (x as usize) >= self.size
-            || (y as usize) >= self.size
-            || self.tile_at(x as usize, y as usize).tile_type == TileType::Blocked
-        {
+        if !step_allowed(self.movement, self.corner_cutting, direction, |x, y| {
+            self.tile_type_at(player_x + x, player_y + y)
+        }) {
+            self.stats.blocked_moves += 1;
             return Err(DirectionBlocked);
         }
 
+        let (x, y) = direction.offset();
         self.player = Position {
-            x: x as usize,
-            y: y as usize,
+            x: (player_x + x) as usize,
+            y: (player_y + y) as usize,
         };
+        self.stats.moves += 1;
 
-        self.reveal_around_player();
-        Ok(())
+        Ok(self.reveal_around_player())
     }
 
     pub fn neighbouring_tile_types(&self) -> NeighbouringTileTypes {
         let player_x = self.player.x as i32;
         let player_y = self.player.y as i32;
 
-        NeighbouringTileTypes {
-            left: self.tile_type_at(player_x - 1, player_y),
-            right: self.tile_type_at(player_x + 1, player_y),
-            up: self.tile_type_at(player_x, player_y - 1),
-            down: self.tile_type_at(player_x, player_y + 1),
-        }
+        NeighbouringTileTypes::around(self.movement, self.corner_cutting, |x, y| {
+            self.tile_type_at(player_x + x, player_y + y)
+        })
+    }
+
+    /// Switches between orthogonal and diagonal movement. Diagonal movement also reveals and
+    /// reports the diagonal neighbours of the player.
+    pub fn set_movement(&mut self, movement: Movement) {
+        self.movement = movement;
+        self.reveal_around_player();
+    }
+
+    /// Sets whether diagonal steps may pass between blocked tiles.
+    pub fn set_corner_cutting(&mut self, corner_cutting: CornerCutting) {
+        self.corner_cutting = corner_cutting;
+    }
+
+    pub fn movement(&self) -> Movement {
+        self.movement
+    }
+
+    pub fn corner_cutting(&self) -> CornerCutting {
+        self.corner_cutting
+    }
+
+    /// Looks along each direction the player can move in, revealing every tile up to and including
+    /// the next wall. Returns how many open tiles lie before each of the walls.
+    pub fn look(&mut self) -> Probe {
+        self.probes += 1;
+
+        let (player_x, player_y) = (self.player.x as i32, self.player.y as i32);
+        Probe::new(self.movement, self.probe_cost, |direction| {
+            let (dx, dy) = direction.offset();
+            let (mut x, mut y) = (player_x, player_y);
+            let mut distance = 0;
+
+            while step_allowed(self.movement, self.corner_cutting, direction, |sx, sy| {
+                self.tile_type_at(x + sx, y + sy)
+            }) {
+                x += dx;
+                y += dy;
+                self.reveal(x as usize, y as usize);
+                distance += 1;
+            }
+
+            let (wall_x, wall_y) = (x + dx, y + dy);
+            if self.contains(wall_x, wall_y) {
+                self.reveal(wall_x as usize, wall_y as usize);
+            }
+            distance
+        })
+    }
+
+    /// Sets how much each call to `look` costs. Probing is free unless a cost is set.
+    pub fn set_probe_cost(&mut self, cost: usize) {
+        self.probe_cost = cost;
+    }
+
+    pub fn probe_cost(&self) -> usize {
+        self.probe_cost
+    }
+
+    /// How many times the player has probed with `look`.
+    pub fn probes(&self) -> usize {
+        self.probes
     }
 
     pub fn player(&self) -> Position {
         self.player
     }
 
-    fn reveal_around_player(&mut self) {
-        self.reveal(self.player.x, self.player.y);
-        if self.player.x > 0 {
-            self.reveal(self.player.x - 1, self.player.y);
+    pub fn exit(&self) -> Position {
+        self.exit
+    }
+
+    /// What the player has done so far, for scoring the game.
+    pub fn stats(&self) -> GameStats {
+        self.stats
+    }
+
+    /// The number of tiles in each row of the maze.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The number of rows of the maze.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn status(&self) -> GameStatus {
+        if self.player == self.exit {
+            GameStatus::Won
+        } else {
+            GameStatus::Playing
         }
-        if self.player.y > 0 {
-            self.reveal(self.player.x, self.player.y - 1);
+    }
+
+    /// The `(2 * radius + 1)` tiles wide square around the player, with the radius lowered to
+    /// `MAX_VIEW_RADIUS` if it is larger.
+    pub fn local_view(&self, radius: usize) -> LocalView {
+        let (player_x, player_y) = (self.player.x as i32, self.player.y as i32);
+        let radius = radius.min(MAX_VIEW_RADIUS) as i32;
+
+        LocalView::new(
+            (player_y - radius..=player_y + radius)
+                .flat_map(|y| (player_x - radius..=player_x + radius).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    if self.contains(x, y) {
+                        self.map_tile_at(x as usize, y as usize)
+                    } else {
+                        MapTile::Blocked
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// The map as seen by the player, row by row with one `MapTile` per byte.
+    pub fn tiles(&self) -> Vec<u8> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| self.map_tile_at(x, y) as u8)
+            .collect()
+    }
+
+    /// Whether the tiles of this maze are stored in the compact bitset representation.
+    pub fn is_compact(&self) -> bool {
+        self.map.is_compact()
+    }
+
+    /// Reveals the tiles around the player and returns the positions of those that were hidden.
+    fn reveal_around_player(&mut self) -> Vec<Position> {
+        let mut revealed = Vec::new();
+        let player = self.player;
+
+        if self.reveal(player.x, player.y) {
+            revealed.push(player);
         }
-        if self.player.x < self.size - 1 {
-            self.reveal(self.player.x + 1, self.player.y);
+        for direction in self.movement.directions() {
+            let (x, y) = direction.offset();
+            let x = player.x as i32 + x;
+            let y = player.y as i32 + y;
+            if self.contains(x, y) && self.reveal(x as usize, y as usize) {
+                revealed.push(Position {
+                    x: x as usize,
+                    y: y as usize,
+                });
+            }
         }
-        if self.player.y < self.size - 1 {
-            self.reveal(self.player.x, self.player.y + 1);
+        revealed
+    }
+}
+
+impl Maze {
+    /// The fewest moves needed to get from the start in the top left corner to the exit under the
+    /// current movement rules, or `None` if the exit cannot be reached.
+    pub fn optimal_moves(&self) -> Option<usize> {
+        let mut distances = vec![None; self.width * self.height];
+        let mut queue = VecDeque::new();
+
+        distances[0] = Some(0);
+        queue.push_back(Position { x: 0, y: 0 });
+
+        while let Some(position) = queue.pop_front() {
+            let distance = distances[self.to_index(position.x, position.y)].unwrap();
+            if position == self.exit {
+                return Some(distance);
+            }
+
+            let (x, y) = (position.x as i32, position.y as i32);
+            for &direction in self.movement.directions() {
+                if !step_allowed(self.movement, self.corner_cutting, direction, |dx, dy| {
+                    self.tile_type_at(x + dx, y + dy)
+                }) {
+                    continue;
+                }
+
+                let (dx, dy) = direction.offset();
+                let next = Position {
+                    x: (x + dx) as usize,
+                    y: (y + dy) as usize,
+                };
+                let i = self.to_index(next.x, next.y);
+                if distances[i].is_none() {
+                    distances[i] = Some(distance + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Gathers the inputs for scoring the game so far, given how long it has been played for.
+    pub fn score_inputs(&self, elapsed: Duration) -> ScoreInputs {
+        ScoreInputs {
+            moves: self.stats.moves,
+            optimal_moves: self.optimal_moves().unwrap_or(0),
+            blocked_moves: self.stats.blocked_moves,
+            tiles_revealed: self.stats.tiles_revealed,
+            probe_cost: self.probes.saturating_mul(self.probe_cost),
+            elapsed,
+        }
+    }
+
+    /// Takes each of the moves in `directions` in turn, stopping at the first one that is blocked.
+    /// Returns how far the player got and every tile revealed on the way.
+    pub fn apply_moves(&mut self, directions: &[Direction]) -> MovesApplied<Position> {
+        let mut applied = MovesApplied {
+            moved: 0,
+            blocked: false,
+            revealed: Vec::new(),
+        };
+
+        for &direction in directions {
+            match self.step(direction) {
+                Ok(revealed) => {
+                    applied.moved += 1;
+                    applied
+                        .revealed
+                        .extend(revealed.into_iter().map(|position| RevealedTile {
+                            position,
+                            tile: self.tile_at(position.x, position.y).into(),
+                        }));
+                }
+                Err(DirectionBlocked) => {
+                    applied.blocked = true;
+                    break;
+                }
+            }
         }
+
+        applied
+    }
+}
+
+impl Probe {
+    /// Builds the probe from the `distance` to the next wall in each direction.
+    fn new(movement: Movement, cost: usize, mut distance: impl FnMut(Direction) -> usize) -> Self {
+        let mut diagonal = |direction| match movement {
+            Movement::Orthogonal => None,
+            Movement::Diagonal => Some(distance(direction)),
+        };
+        let up_left = diagonal(Direction::UpLeft);
+        let up_right = diagonal(Direction::UpRight);
+        let down_left = diagonal(Direction::DownLeft);
+        let down_right = diagonal(Direction::DownRight);
+
+        Probe {
+            left: distance(Direction::Left),
+            right: distance(Direction::Right),
+            up: distance(Direction::Up),
+            down: distance(Direction::Down),
+            up_left,
+            up_right,
+            down_left,
+            down_right,
+            cost,
+        }
+    }
+}
+
+impl LocalView {
+    fn new(tiles: Vec<MapTile>) -> Self {
+        let size = (tiles.len() as f64).sqrt() as usize;
+        debug_assert_eq!(size * size, tiles.len());
+        LocalView { size, tiles }
+    }
+
+    /// The tile at `x` and `y`, counted from the top left corner of the view.
+    pub fn tile(&self, x: usize, y: usize) -> MapTile {
+        self.tiles[self.size * y + x]
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+impl LocalView {
+    /// The number of tiles along each side of the view.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The view row by row with one `MapTile` per byte.
+    pub fn tiles(&self) -> Vec<u8> {
+        self.tiles.iter().map(|&tile| tile as u8).collect()
     }
 }
 
@@ -328,9 +667,7 @@ impl Serialize for Maze {
         // To serialise a single row without copying all the elements into a new array.
         struct Row<'a> {
             row_index: usize,
-            player: &'a Position,
-            exit: &'a Position,
-            elements: &'a [Tile],
+            maze: &'a Maze,
         }
 
         impl<'a> Serialize for Row<'a> {
@@ -338,49 +675,66 @@ impl Serialize for Maze {
             where
                 S: Serializer,
             {
-                let mut seq = serializer.serialize_seq(Some(self.elements.len()))?;
-                for x in 0..self.elements.len() {
-                    if self.player.x == x && self.player.y == self.row_index {
-                        seq.serialize_element("player")?;
-                    } else if self.exit.x == x && self.exit.y == self.row_index {
-                        seq.serialize_element("exit")?;
-                    } else {
-                        seq.serialize_element(&self.elements[x])?;
-                    }
+                let maze = self.maze;
+                let mut seq = serializer.serialize_seq(Some(maze.width))?;
+                for x in 0..maze.width {
+                    seq.serialize_element(&maze.map_tile_at(x, self.row_index))?;
                 }
                 seq.end()
             }
         }
 
-        let mut seq = serializer.serialize_seq(Some(self.size))?;
-        for y in 0..self.size {
+        let mut seq = serializer.serialize_seq(Some(self.height))?;
+        for y in 0..self.height {
             seq.serialize_element(&Row {
                 row_index: y,
-                player: &self.player,
-                exit: &self.exit,
-                elements: &self.map[(y * self.size)..(y * self.size) + self.size],
+                maze: self,
             })?;
         }
         seq.end()
     }
 }
 
+impl Serialize for LocalView {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.tiles.chunks(self.size))
+    }
+}
+
 impl Serialize for Tile {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        if self.is_revealed() {
-            match self.tile_type {
-                TileType::Open => serializer.serialize_str("open"),
-                TileType::Blocked => serializer.serialize_str("blocked"),
-            }
-        } else {
-            serializer.serialize_str("hidden")
+        MapTile::from(*self).serialize(serializer)
+    }
+}
+
+impl From<Tile> for MapTile {
+    fn from(tile: Tile) -> Self {
+        match (tile.is_revealed(), tile.tile_type) {
+            (false, _) => MapTile::Hidden,
+            (true, TileType::Open) => MapTile::Open,
+            (true, TileType::Blocked) => MapTile::Blocked,
         }
     }
 }
 
+impl FromStr for Direction {
+    type Err = UnknownDirection;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Direction::ALL
+            .iter()
+            .copied()
+            .find(|direction| direction.to_string() == s)
+            .ok_or(UnknownDirection)
+    }
+}
+
 impl fmt::Display for Direction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use Direction::*;
@@ -392,6 +746,10 @@ impl fmt::Display for Direction {
                 Down => "down",
                 Left => "left",
                 Right => "right",
+                UpLeft => "up-left",
+                UpRight => "up-right",
+                DownLeft => "down-left",
+                DownRight => "down-right",
             }
         )
     }
@@ -411,8 +769,14 @@ pub mod tests {
                 x: size - 1,
                 y: size - 1,
             },
-            size,
-            map: Vec::from(map),
+            width: size,
+            height: size,
+            map: TileMap::dense(map.iter().copied()),
+            movement: Movement::default(),
+            corner_cutting: CornerCutting::default(),
+            probe_cost: 0,
+            probes: 0,
+            stats: GameStats::default(),
         };
 
         maze.reveal_around_player();
@@ -429,33 +793,200 @@ pub mod tests {
         }
     }
 
+    #[test]
+    /// A compact maze has the same number of tiles as a regular one and keeps the start and exit
+    /// open.
+    fn creating_compact_maze_with_size() {
+        for size in (1..100).filter(|x| x & 1 != 0) {
+            let maze = Maze::new_compact(size);
+            assert!(maze.is_compact());
+            assert_eq!(maze.map.len(), size * size);
+            assert_eq!(maze.tile_at(0, 0).tile_type, TileType::Open);
+            assert_eq!(maze.tile_at(size - 1, size - 1).tile_type, TileType::Open);
+        }
+    }
+
     #[test]
     /// Start and exit tile are not blocked in a random maze
     fn random_maze_start_and_exit_not_blocked() {
         for size in (1..100).filter(|x| x & 1 != 0) {
             let maze = Maze::new(size);
 
-            let start_tile_type = maze.map[maze.to_index(0, 0)].tile_type;
-            let end_tile_type = maze.map[maze.to_index(size - 1, size - 1)].tile_type;
+            let start_tile_type = maze.tile_at(0, 0).tile_type;
+            let end_tile_type = maze.tile_at(size - 1, size - 1).tile_type;
 
             assert_eq!(start_tile_type, TileType::Open);
             assert_eq!(end_tile_type, TileType::Open);
         }
     }
 
+    #[test]
+    /// The typed map has one byte per tile in the same order as the serialized map.
+    fn tiles_match_serialized_map() {
+        let maze = Maze::new(9);
+        let names = ["hidden", "open", "blocked", "player", "exit"];
+
+        let serialized: Vec<Vec<String>> =
+            serde_json::from_str(&serde_json::to_string(&maze).unwrap()).unwrap();
+        let tiles = maze
+            .tiles()
+            .into_iter()
+            .map(|tile| names[tile as usize])
+            .collect::<Vec<_>>();
+
+        assert_eq!(tiles, serialized.concat());
+    }
+
+    #[test]
+    /// The game is only won once the player stands on the exit.
+    fn status_won_at_exit() {
+        let mut maze = maze_from_slice_with_player_at(0, 0, &[Tile::open(); 4]);
+        assert_eq!(maze.status(), GameStatus::Playing);
+
+        maze.move_player(Direction::Right).unwrap();
+        maze.move_player(Direction::Down).unwrap();
+        assert_eq!(maze.status(), GameStatus::Won);
+    }
+
+    #[test]
+    /// Moves, blocked moves and newly revealed tiles are counted as the player moves.
+    fn stats_count_moves() {
+        let mut maze = maze_from_slice_with_player_at(0, 0, &[Tile::open(); 9]);
+        assert_eq!(maze.stats().tiles_revealed, 3);
+
+        maze.move_player(Direction::Right).unwrap();
+        maze.move_player(Direction::Up).unwrap_err();
+        maze.move_player(Direction::Left).unwrap();
+
+        assert_eq!(
+            maze.stats(),
+            GameStats {
+                moves: 2,
+                blocked_moves: 1,
+                tiles_revealed: 5,
+            }
+        );
+    }
+
+    #[test]
+    /// The optimal number of moves follows the shortest path through the maze and takes diagonal
+    /// movement into account.
+    fn optimal_moves_follow_shortest_path() {
+        let mut map = vec![Tile::open(); 3 * 3];
+        map[1] = Tile::blocked();
+        map[3 + 1] = Tile::blocked();
+        let mut maze = maze_from_slice_with_player_at(0, 0, &map);
+
+        assert_eq!(maze.optimal_moves(), Some(4));
+
+        maze.set_movement(Movement::Diagonal);
+        assert_eq!(maze.optimal_moves(), Some(4));
+
+        maze.set_corner_cutting(CornerCutting::OneSideOpen);
+        assert_eq!(maze.optimal_moves(), Some(3));
+
+        map[3 * 2 + 1] = Tile::blocked();
+        let maze = maze_from_slice_with_player_at(0, 0, &map);
+        assert_eq!(maze.optimal_moves(), None);
+    }
+
+    #[test]
+    /// Every generated maze can be solved.
+    fn random_maze_is_solvable() {
+        for size in (1..50).filter(|x| x & 1 != 0) {
+            let maze = Maze::new(size);
+            assert!(maze.optimal_moves().is_some());
+        }
+    }
+
+    #[test]
+    /// The local view is centred on the player, hides unseen tiles and treats everything outside
+    /// of the maze as blocked.
+    fn local_view_around_player() {
+        let maze = maze_from_slice_with_player_at(0, 0, &[Tile::open(); 9]);
+        let view = maze.local_view(2);
+
+        assert_eq!(view.size(), 5);
+        assert_eq!(view.tile(2, 2), MapTile::Player);
+        assert_eq!(view.tile(3, 2), MapTile::Open);
+        assert_eq!(view.tile(3, 3), MapTile::Hidden);
+        assert_eq!(view.tile(4, 4), MapTile::Exit);
+        assert_eq!(view.tile(1, 2), MapTile::Blocked);
+        assert_eq!(view.tile(0, 0), MapTile::Blocked);
+        assert_eq!(
+            serde_json::to_string(&maze.local_view(1)).unwrap(),
+            r#"[["blocked","blocked","blocked"],["blocked","player","open"],["blocked","open","hidden"]]"#
+        );
+    }
+
+    #[test]
+    /// Radii beyond the largest view are lowered to it rather than overflowing.
+    fn local_view_radius_capped() {
+        let maze = maze_from_slice_with_player_at(0, 0, &[Tile::open(); 9]);
+
+        let view = maze.local_view(usize::MAX);
+
+        assert_eq!(view.size(), 2 * MAX_VIEW_RADIUS + 1);
+        assert_eq!(view.tile(MAX_VIEW_RADIUS, MAX_VIEW_RADIUS), MapTile::Player);
+    }
+
+    #[test]
+    /// Looking counts the open tiles up to the next wall in every direction and reveals them.
+    fn look_reveals_corridors() {
+        let mut map = vec![Tile::open(); 5 * 5];
+        map[3] = Tile::blocked();
+        map[2 * 5 + 1] = Tile::blocked();
+
+        let mut maze = maze_from_slice_with_player_at(1, 0, &map);
+        maze.set_probe_cost(3);
+        let probe = maze.look();
+
+        assert_eq!(
+            probe,
+            Probe {
+                left: 1,
+                right: 1,
+                up: 0,
+                down: 1,
+                up_left: None,
+                up_right: None,
+                down_left: None,
+                down_right: None,
+                cost: 3,
+            }
+        );
+        assert_eq!(maze.probes(), 1);
+        assert!(maze.tile_at(3, 0).is_revealed());
+        assert!(maze.tile_at(1, 2).is_revealed());
+        assert!(!maze.tile_at(4, 0).is_revealed());
+    }
+
+    #[test]
+    /// The cost of probes stops at the largest cost rather than overflowing.
+    fn probe_cost_saturates() {
+        let mut maze = Maze::new(5);
+        maze.set_probe_cost(usize::MAX);
+        maze.look();
+        maze.look();
+
+        let inputs = maze.score_inputs(Duration::from_secs(0));
+
+        assert_eq!(inputs.probe_cost, usize::MAX);
+    }
+
     #[test]
     /// The map maze should serialize to a 2d array instead of its internal representation.
     fn mazemap_serializes_to_a_2d_array() {
         fn set(maze: &mut Maze, x: usize, y: usize, cell: Tile) {
             let i = maze.to_index(x, y);
-            maze.map[i] = cell;
-        };
+            maze.map.set(i, cell);
+        }
 
         let test_cases = [
             (3, r#"[["player","hidden","blocked"],["hidden","blocked","blocked"],["blocked","blocked","exit"]]"#),
             (2, r#"[["player","hidden"],["hidden","exit"]]"#),
         ];
-        for &(size, expected) in test_cases.into_iter() {
+        for &(size, expected) in test_cases.iter() {
             let mut blocked = Tile::blocked();
             blocked.reveal();
             let mut open = Tile::open();
@@ -498,6 +1029,10 @@ mod neighbouring_tile_types {
             right: TileType::Open,
             up: TileType::Blocked,
             down: TileType::Open,
+            up_left: None,
+            up_right: None,
+            down_left: None,
+            down_right: None,
         };
         assert_eq!(tile_types_actual, tile_types_should_be);
     }
@@ -511,6 +1046,10 @@ mod neighbouring_tile_types {
             right: TileType::Open,
             up: TileType::Open,
             down: TileType::Open,
+            up_left: None,
+            up_right: None,
+            down_left: None,
+            down_right: None,
         };
         assert_eq!(tile_types_actual, tile_types_should_be);
     }
@@ -524,6 +1063,10 @@ mod neighbouring_tile_types {
             right: TileType::Blocked,
             up: TileType::Open,
             down: TileType::Blocked,
+            up_left: None,
+            up_right: None,
+            down_left: None,
+            down_right: None,
         };
         assert_eq!(tile_types_actual, tile_types_should_be);
     }
@@ -531,7 +1074,10 @@ mod neighbouring_tile_types {
 
 #[cfg(test)]
 mod move_player {
-    use super::{Direction, DirectionBlocked, Maze, Position, Tile};
+    use super::{
+        CornerCutting, Direction, DirectionBlocked, GameStats, MapTile, Maze, Movement, Position,
+        RevealedTile, Tile, TileMap, UnknownDirection,
+    };
     use lazy_static::lazy_static;
 
     pub fn maze_from_slice_with_player_at(x: usize, y: usize, map: &[Tile]) -> Maze {
@@ -543,8 +1089,14 @@ mod move_player {
                 x: size - 1,
                 y: size - 1,
             },
-            size,
-            map: Vec::from(map),
+            width: size,
+            height: size,
+            map: TileMap::dense(map.iter().copied()),
+            movement: Movement::default(),
+            corner_cutting: CornerCutting::default(),
+            probe_cost: 0,
+            probes: 0,
+            stats: GameStats::default(),
         }
     }
 
@@ -666,6 +1218,96 @@ mod move_player {
             assert_eq!(maze.player.y, 1);
         }
 
+        #[test]
+        fn diagonal_direction_is_blocked_without_diagonal_movement() {
+            let mut maze = maze_from_slice_with_player_at(1, 1, &[Tile::open(); 3 * 3]);
+            let err = maze.move_player(Direction::UpLeft);
+
+            assert_eq!(err, Err(DirectionBlocked));
+            assert_eq!(maze.player.x, 1);
+            assert_eq!(maze.player.y, 1);
+        }
+
+        #[test]
+        fn open_diagonal_direction_player_is_moved() {
+            let mut maze = maze_from_slice_with_player_at(1, 1, &[Tile::open(); 3 * 3]);
+            maze.set_movement(Movement::Diagonal);
+            maze.move_player(Direction::DownRight).unwrap();
+
+            assert_eq!(maze.player.x, 2);
+            assert_eq!(maze.player.y, 2);
+        }
+
+        #[test]
+        fn diagonal_direction_between_walls_follows_corner_cutting() {
+            let mut map = [Tile::open(); 3 * 3];
+            map[1] = Tile::blocked();
+            map[3] = Tile::blocked();
+
+            let mut maze = maze_from_slice_with_player_at(1, 1, &map);
+            maze.set_movement(Movement::Diagonal);
+            assert_eq!(maze.move_player(Direction::UpLeft), Err(DirectionBlocked));
+
+            maze.set_corner_cutting(CornerCutting::Always);
+            maze.move_player(Direction::UpLeft).unwrap();
+            assert_eq!(maze.player.x, 0);
+            assert_eq!(maze.player.y, 0);
+        }
+
+        #[test]
+        fn apply_moves_stops_at_first_blocked_move() {
+            let mut map = [Tile::open(); 4 * 4];
+            map[2] = Tile::blocked();
+
+            let mut maze = maze_from_slice_with_player_at(0, 0, &map);
+            let applied = maze.apply_moves(&[
+                Direction::Down,
+                Direction::Up,
+                Direction::Right,
+                Direction::Right,
+                Direction::Down,
+            ]);
+
+            assert_eq!(applied.moved, 3);
+            assert!(applied.blocked);
+            assert_eq!(maze.player.x, 1);
+            assert_eq!(maze.player.y, 0);
+        }
+
+        #[test]
+        fn apply_moves_reports_revealed_tiles() {
+            let mut map = [Tile::open(); 4 * 4];
+            map[2] = Tile::blocked();
+
+            let mut maze = maze_from_slice_with_player_at(0, 0, &map);
+            maze.reveal_around_player();
+            let applied = maze.apply_moves(&[Direction::Right]);
+
+            assert_eq!(applied.moved, 1);
+            assert!(!applied.blocked);
+            assert_eq!(
+                applied.revealed,
+                vec![
+                    RevealedTile {
+                        position: Position { x: 1, y: 1 },
+                        tile: MapTile::Open,
+                    },
+                    RevealedTile {
+                        position: Position { x: 2, y: 0 },
+                        tile: MapTile::Blocked,
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn direction_parses_from_its_name() {
+            for &direction in Direction::ALL.iter() {
+                assert_eq!(direction.to_string().parse(), Ok(direction));
+            }
+            assert_eq!("sideways".parse::<Direction>(), Err(UnknownDirection));
+        }
+
         #[test]
         fn edge_direction_up_player_is_moved() {
             let direction = Direction::Up;
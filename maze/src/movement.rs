@@ -0,0 +1,223 @@
+use super::{Direction, NeighbouringTileTypes, TileType};
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+/// Which directions the player is allowed to step in.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Movement {
+    /// Only up, down, left and right.
+    #[default]
+    Orthogonal,
+    /// The four orthogonal directions and the four diagonals between them.
+    Diagonal,
+}
+
+/// Whether a diagonal step may pass the corner between the two orthogonal tiles next to it.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CornerCutting {
+    /// Both orthogonal tiles must be open.
+    #[default]
+    Never,
+    /// At least one of the orthogonal tiles must be open.
+    OneSideOpen,
+    /// The step is allowed even when both orthogonal tiles are blocked.
+    Always,
+}
+
+impl Direction {
+    pub(crate) const ORTHOGONAL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    pub(crate) const ALL: [Direction; 8] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+        Direction::UpLeft,
+        Direction::UpRight,
+        Direction::DownLeft,
+        Direction::DownRight,
+    ];
+
+    /// The change in x and y of a single step in this direction.
+    pub(crate) fn offset(self) -> (i32, i32) {
+        use Direction::*;
+
+        match self {
+            Up => (0, -1),
+            Down => (0, 1),
+            Left => (-1, 0),
+            Right => (1, 0),
+            UpLeft => (-1, -1),
+            UpRight => (1, -1),
+            DownLeft => (-1, 1),
+            DownRight => (1, 1),
+        }
+    }
+
+    pub(crate) fn is_diagonal(self) -> bool {
+        let (x, y) = self.offset();
+        x != 0 && y != 0
+    }
+}
+
+impl Movement {
+    /// The directions whose neighbouring tiles the player can see and step onto.
+    pub(crate) fn directions(self) -> &'static [Direction] {
+        match self {
+            Movement::Orthogonal => &Direction::ORTHOGONAL,
+            Movement::Diagonal => &Direction::ALL,
+        }
+    }
+}
+
+impl CornerCutting {
+    fn allows(self, horizontal: TileType, vertical: TileType) -> bool {
+        match self {
+            CornerCutting::Never => horizontal == TileType::Open && vertical == TileType::Open,
+            CornerCutting::OneSideOpen => {
+                horizontal == TileType::Open || vertical == TileType::Open
+            }
+            CornerCutting::Always => true,
+        }
+    }
+}
+
+/// Whether the player may take a step in `direction`. `tile_type` returns the type of the tile at
+/// the given offset from the player.
+pub(crate) fn step_allowed(
+    movement: Movement,
+    corner_cutting: CornerCutting,
+    direction: Direction,
+    tile_type: impl Fn(i32, i32) -> TileType,
+) -> bool {
+    let (x, y) = direction.offset();
+
+    if tile_type(x, y) == TileType::Blocked {
+        false
+    } else if direction.is_diagonal() {
+        movement == Movement::Diagonal && corner_cutting.allows(tile_type(x, 0), tile_type(0, y))
+    } else {
+        true
+    }
+}
+
+impl NeighbouringTileTypes {
+    /// The neighbouring tiles of the player. `tile_type` returns the type of the tile at the given
+    /// offset from the player. Diagonals are only reported when diagonal movement is enabled and
+    /// are blocked whenever the corner cutting rules forbid stepping onto them.
+    pub(crate) fn around(
+        movement: Movement,
+        corner_cutting: CornerCutting,
+        tile_type: impl Fn(i32, i32) -> TileType,
+    ) -> Self {
+        let diagonal = |direction| match movement {
+            Movement::Orthogonal => None,
+            Movement::Diagonal => Some(
+                if step_allowed(movement, corner_cutting, direction, &tile_type) {
+                    TileType::Open
+                } else {
+                    TileType::Blocked
+                },
+            ),
+        };
+
+        NeighbouringTileTypes {
+            left: tile_type(-1, 0),
+            right: tile_type(1, 0),
+            up: tile_type(0, -1),
+            down: tile_type(0, 1),
+            up_left: diagonal(Direction::UpLeft),
+            up_right: diagonal(Direction::UpRight),
+            down_left: diagonal(Direction::DownLeft),
+            down_right: diagonal(Direction::DownRight),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x3 neighbourhood with the player in the middle, described row by row with `#` for blocked
+    /// tiles.
+    fn tiles(rows: [&'static str; 3]) -> impl Fn(i32, i32) -> TileType {
+        move |x, y| match rows[(y + 1) as usize].as_bytes()[(x + 1) as usize] {
+            b'#' => TileType::Blocked,
+            _ => TileType::Open,
+        }
+    }
+
+    #[test]
+    /// Diagonal steps are never allowed in orthogonal mode.
+    fn orthogonal_movement_forbids_diagonals() {
+        let tile_type = tiles(["...", "...", "..."]);
+        for &direction in &Direction::ALL {
+            assert_eq!(
+                step_allowed(
+                    Movement::Orthogonal,
+                    CornerCutting::Always,
+                    direction,
+                    &tile_type
+                ),
+                !direction.is_diagonal()
+            );
+        }
+    }
+
+    #[test]
+    /// The corner cutting rules decide whether a diagonal step may squeeze past walls.
+    fn corner_cutting_rules() {
+        let both_blocked = tiles(["..#", "..#", "##."]);
+        let one_blocked = tiles(["...", "..#", "..."]);
+        let cases = [
+            (CornerCutting::Never, &both_blocked, false),
+            (CornerCutting::Never, &one_blocked, false),
+            (CornerCutting::OneSideOpen, &both_blocked, false),
+            (CornerCutting::OneSideOpen, &one_blocked, true),
+            (CornerCutting::Always, &both_blocked, true),
+            (CornerCutting::Always, &one_blocked, true),
+        ];
+
+        for &(corner_cutting, tile_type, allowed) in cases.iter() {
+            assert_eq!(
+                step_allowed(
+                    Movement::Diagonal,
+                    corner_cutting,
+                    Direction::DownRight,
+                    tile_type
+                ),
+                allowed,
+                "{:?}",
+                corner_cutting
+            );
+        }
+    }
+
+    #[test]
+    /// Diagonal neighbours are only reported in diagonal mode and respect the corner cutting rules.
+    fn neighbours_report_diagonals() {
+        let tile_type = tiles(["#..", "..#", "..."]);
+
+        let orthogonal =
+            NeighbouringTileTypes::around(Movement::Orthogonal, CornerCutting::Never, &tile_type);
+        assert_eq!(orthogonal.up_left, None);
+
+        let diagonal =
+            NeighbouringTileTypes::around(Movement::Diagonal, CornerCutting::Never, &tile_type);
+        assert_eq!(diagonal.up_left, Some(TileType::Blocked));
+        assert_eq!(diagonal.up_right, Some(TileType::Blocked));
+        assert_eq!(diagonal.down_left, Some(TileType::Open));
+        assert_eq!(diagonal.down_right, Some(TileType::Blocked));
+    }
+}
@@ -0,0 +1,291 @@
+use super::{CornerCutting, Direction, InvalidSize, Maze, MazeConfig, Movement};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+/// A single turn of a recorded game: what the player did, when they did it and how far it got
+/// them.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Turn {
+    /// How long after the start of the game the turn was taken.
+    pub at_ms: u64,
+    pub play: Play,
+    /// How many steps the player took, fewer than asked for when a wall was in the way.
+    #[serde(default)]
+    pub moved: usize,
+}
+
+/// What the player did in a turn, such as `{"move": "up"}`, `{"moves": ["up", "left"]}` or
+/// `"look"`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Play {
+    Move(Direction),
+    Moves(Vec<Direction>),
+    Look,
+}
+
+/// The settings of a recorded game that decide how its turns play out. Other settings, such as
+/// how the game was scored, are ignored.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ReplayConfig {
+    #[serde(flatten)]
+    pub maze: MazeConfig,
+    #[serde(default)]
+    pub movement: Movement,
+    #[serde(default)]
+    pub corner_cutting: CornerCutting,
+    #[serde(default)]
+    pub probe_cost: usize,
+}
+
+/// A recorded game of a bounded maze as returned by `/replay`: the settings to regenerate the maze
+/// from and every turn taken in it.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Replay {
+    pub config: ReplayConfig,
+    pub turns: Vec<Turn>,
+}
+
+#[derive(Debug, Display, PartialEq)]
+pub enum ReplayError {
+    #[display(fmt = "{}", _0)]
+    InvalidSize(InvalidSize),
+    /// A turn did not play out the way it was recorded, so the replay does not belong to the maze
+    /// its settings generate.
+    #[display(
+        fmt = "turn {} moved {} steps but {} were recorded",
+        turn,
+        moved,
+        recorded
+    )]
+    Diverged {
+        turn: usize,
+        moved: usize,
+        recorded: usize,
+    },
+}
+
+/// Plays a replay back one turn at a time. Any state in between can be reached with `seek`, which
+/// starts over from the generated maze when going backwards.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Clone)]
+pub struct ReplayPlayer {
+    replay: Replay,
+    start: Maze,
+    maze: Maze,
+    turn: usize,
+}
+
+impl From<InvalidSize> for ReplayError {
+    fn from(error: InvalidSize) -> Self {
+        ReplayError::InvalidSize(error)
+    }
+}
+
+impl ReplayPlayer {
+    /// Regenerates the maze of `replay`, ready to play its first turn.
+    pub fn new(replay: Replay) -> Result<Self, ReplayError> {
+        let mut start = replay.config.maze.generate()?;
+        start.set_movement(replay.config.movement);
+        start.set_corner_cutting(replay.config.corner_cutting);
+        start.set_probe_cost(replay.config.probe_cost);
+
+        Ok(ReplayPlayer {
+            maze: start.clone(),
+            start,
+            replay,
+            turn: 0,
+        })
+    }
+
+    /// The maze as it was after the turns played so far.
+    pub fn maze(&self) -> &Maze {
+        &self.maze
+    }
+
+    /// How many turns have been played so far.
+    pub fn turn(&self) -> usize {
+        self.turn
+    }
+
+    pub fn turns(&self) -> &[Turn] {
+        &self.replay.turns
+    }
+
+    /// Plays the next turn and returns it, or `None` once every turn has been played.
+    pub fn step(&mut self) -> Result<Option<&Turn>, ReplayError> {
+        let turn = match self.replay.turns.get(self.turn) {
+            Some(turn) => turn,
+            None => return Ok(None),
+        };
+
+        let moved = match &turn.play {
+            Play::Move(direction) => match self.maze.internal_move_player(*direction) {
+                Ok(()) => 1,
+                Err(_) => 0,
+            },
+            Play::Moves(directions) => self.maze.apply_moves(directions).moved,
+            Play::Look => {
+                self.maze.look();
+                0
+            }
+        };
+        if moved != turn.moved {
+            return Err(ReplayError::Diverged {
+                turn: self.turn,
+                moved,
+                recorded: turn.moved,
+            });
+        }
+
+        self.turn += 1;
+        Ok(Some(turn))
+    }
+
+    /// Moves to the state after the first `turn` turns, or after the last turn if there are fewer.
+    pub fn seek(&mut self, turn: usize) -> Result<(), ReplayError> {
+        if turn < self.turn {
+            self.maze = self.start.clone();
+            self.turn = 0;
+        }
+        while self.turn < turn && self.step()?.is_some() {}
+        Ok(())
+    }
+
+    /// Moves to the state `at_ms` into the game, after every turn taken by then.
+    pub fn seek_time(&mut self, at_ms: u64) -> Result<(), ReplayError> {
+        let turn = self
+            .replay
+            .turns
+            .iter()
+            .take_while(|turn| turn.at_ms <= at_ms)
+            .count();
+        self.seek(turn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Generator, Visibility};
+
+    /// A replay of a fogged 9x9 maze, playing `turns` with a turn every 100ms.
+    fn replay(turns: Vec<(Play, usize)>) -> Replay {
+        Replay {
+            config: ReplayConfig {
+                maze: MazeConfig {
+                    width: 9,
+                    height: 9,
+                    seed: 7,
+                    generator: Generator::Backtracker,
+                    visibility: Visibility::Fog,
+                },
+                movement: Movement::default(),
+                corner_cutting: CornerCutting::default(),
+                probe_cost: 0,
+            },
+            turns: turns
+                .into_iter()
+                .enumerate()
+                .map(|(i, (play, moved))| Turn {
+                    at_ms: i as u64 * 100,
+                    play,
+                    moved,
+                })
+                .collect(),
+        }
+    }
+
+    /// The turns a player took to walk as far as they could in the first open direction, then
+    /// look around, recorded from a maze generated with the settings of `replay`.
+    fn recorded_turns() -> Vec<(Play, usize)> {
+        let mut maze = replay(Vec::new()).config.maze.generate().unwrap();
+        let probe = maze.look();
+        let (direction, distance) = if probe.right > 0 {
+            (Direction::Right, probe.right)
+        } else {
+            (Direction::Down, probe.down)
+        };
+        vec![
+            (Play::Move(Direction::Up), 0),
+            (Play::Move(direction), 1),
+            (Play::Moves(vec![direction; distance]), distance - 1),
+            (Play::Look, 0),
+        ]
+    }
+
+    #[test]
+    /// Stepping through a replay plays every turn in order and then stops
+    fn step_plays_every_turn() {
+        let mut player = ReplayPlayer::new(replay(recorded_turns())).unwrap();
+
+        let mut played = 0;
+        while player.step().unwrap().is_some() {
+            played += 1;
+        }
+
+        assert_eq!(played, 4);
+        assert_eq!(player.turn(), 4);
+        assert_eq!(player.maze().stats().blocked_moves, 2);
+        assert_eq!(player.maze().probes(), 1);
+    }
+
+    #[test]
+    /// Seeking backwards gives the same state as stepping forwards to the same turn
+    fn seek_reconstructs_intermediate_states() {
+        let mut stepped = ReplayPlayer::new(replay(recorded_turns())).unwrap();
+        stepped.step().unwrap();
+        stepped.step().unwrap();
+
+        let mut seeked = ReplayPlayer::new(replay(recorded_turns())).unwrap();
+        seeked.seek(4).unwrap();
+        seeked.seek_time(150).unwrap();
+
+        assert_eq!(seeked.turn(), 2);
+        assert_eq!(seeked.maze().player(), stepped.maze().player());
+        assert_eq!(seeked.maze().tiles(), stepped.maze().tiles());
+        assert_eq!(seeked.maze().stats(), stepped.maze().stats());
+    }
+
+    #[test]
+    /// Replays whose turns do not play out as recorded are reported rather than played
+    fn diverging_replay_rejected() {
+        let mut player = ReplayPlayer::new(replay(vec![(Play::Move(Direction::Up), 1)])).unwrap();
+
+        assert_eq!(
+            player.step(),
+            Err(ReplayError::Diverged {
+                turn: 0,
+                moved: 0,
+                recorded: 1
+            })
+        );
+    }
+
+    #[test]
+    /// Replays are read from the JSON returned by /replay, ignoring settings that do not change
+    /// how the turns play out
+    fn read_from_json() {
+        let replay: Replay = serde_json::from_str(
+            r#"{
+                "game": "maze",
+                "config": { "width": 9, "height": 9, "seed": 7, "mode": "speed" },
+                "turns": [{ "at_ms": 5, "play": { "move": "up" }, "moved": 0 }]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(replay.config.maze.generator, Generator::Kruskal);
+        assert_eq!(
+            replay.turns,
+            vec![Turn {
+                at_ms: 5,
+                play: Play::Move(Direction::Up),
+                moved: 0
+            }]
+        );
+    }
+}
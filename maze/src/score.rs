@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::time::Duration;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+/// Running counts of what the player has done in a game, kept by `Maze` and `InfiniteMaze`.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize)]
+pub struct GameStats {
+    /// The number of steps the player took.
+    pub moves: usize,
+    /// The number of steps that were refused because the way was blocked.
+    pub blocked_moves: usize,
+    /// The number of tiles that were hidden before the player saw them.
+    pub tiles_revealed: usize,
+}
+
+/// Everything a `ScoringFormula` may base its score on.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct ScoreInputs {
+    pub moves: usize,
+    /// The fewest moves that reach the exit from the start.
+    pub optimal_moves: usize,
+    pub blocked_moves: usize,
+    pub tiles_revealed: usize,
+    /// The total cost of every probe taken with `look`.
+    pub probe_cost: usize,
+    #[serde(rename = "elapsed_ms", serialize_with = "serialize_millis")]
+    pub elapsed: Duration,
+}
+
+/// A finished game's score together with what it was calculated from.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct Score {
+    pub points: u64,
+    #[serde(flatten)]
+    pub inputs: ScoreInputs,
+}
+
+/// How a game is played for points, each mode scoring finished games with its own formula.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Copy, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum GameMode {
+    /// Scored with `StandardScoring`.
+    #[default]
+    Standard,
+    /// Scored with `SpeedScoring`.
+    Speed,
+}
+
+/// Turns the inputs of a finished game into points. Each game mode can rate games differently by
+/// picking its own formula.
+pub trait ScoringFormula {
+    fn points(&self, inputs: &ScoreInputs) -> u64;
+}
+
+/// Rewards taking a path close to the optimal one. The points for a perfect path are scaled down by
+/// how many more moves than necessary were taken, then every blocked move, revealed tile, second
+/// and unit of probe cost subtracts its penalty.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StandardScoring {
+    pub max_points: u64,
+    pub blocked_penalty: u64,
+    pub reveal_penalty: u64,
+    pub second_penalty: u64,
+}
+
+/// Rewards finishing quickly. Games finished within `par` get the full points, slower games get
+/// points in proportion to how far they went over. Blocked moves and probe costs are still
+/// penalised.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpeedScoring {
+    pub max_points: u64,
+    pub par: Duration,
+    pub blocked_penalty: u64,
+}
+
+impl ScoreInputs {
+    pub fn score(self, formula: &dyn ScoringFormula) -> Score {
+        Score {
+            points: formula.points(&self),
+            inputs: self,
+        }
+    }
+}
+
+impl GameMode {
+    /// The formula games in this mode are scored with.
+    pub fn scoring(self) -> Box<dyn ScoringFormula + Send> {
+        match self {
+            GameMode::Standard => Box::new(StandardScoring::default()),
+            GameMode::Speed => Box::new(SpeedScoring::default()),
+        }
+    }
+}
+
+impl Default for StandardScoring {
+    fn default() -> Self {
+        StandardScoring {
+            max_points: 1000,
+            blocked_penalty: 10,
+            reveal_penalty: 1,
+            second_penalty: 1,
+        }
+    }
+}
+
+impl ScoringFormula for StandardScoring {
+    fn points(&self, inputs: &ScoreInputs) -> u64 {
+        let moves = inputs.moves.max(inputs.optimal_moves).max(1) as u64;
+        let optimal_moves = inputs.optimal_moves.max(1) as u64;
+        let path_points = self.max_points * optimal_moves.min(moves) / moves;
+
+        let penalty = self
+            .blocked_penalty
+            .saturating_mul(inputs.blocked_moves as u64)
+            .saturating_add(
+                self.reveal_penalty
+                    .saturating_mul(inputs.tiles_revealed as u64),
+            )
+            .saturating_add(self.second_penalty.saturating_mul(inputs.elapsed.as_secs()))
+            .saturating_add(inputs.probe_cost as u64);
+
+        path_points.saturating_sub(penalty)
+    }
+}
+
+impl Default for SpeedScoring {
+    fn default() -> Self {
+        SpeedScoring {
+            max_points: 1000,
+            par: Duration::from_secs(60),
+            blocked_penalty: 10,
+        }
+    }
+}
+
+impl ScoringFormula for SpeedScoring {
+    fn points(&self, inputs: &ScoreInputs) -> u64 {
+        let par = self.par.as_millis().max(1);
+        let elapsed = inputs.elapsed.as_millis().max(par);
+        let time_points = (u128::from(self.max_points) * par / elapsed) as u64;
+
+        let penalty = self
+            .blocked_penalty
+            .saturating_mul(inputs.blocked_moves as u64)
+            .saturating_add(inputs.probe_cost as u64);
+
+        time_points.saturating_sub(penalty)
+    }
+}
+
+fn serialize_millis<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(moves: usize, optimal_moves: usize) -> ScoreInputs {
+        ScoreInputs {
+            moves,
+            optimal_moves,
+            blocked_moves: 0,
+            tiles_revealed: 0,
+            probe_cost: 0,
+            elapsed: Duration::from_secs(0),
+        }
+    }
+
+    #[test]
+    /// The standard formula gives full points for the optimal path, scales them down for detours
+    /// and subtracts the penalties.
+    fn standard_scoring() {
+        let scoring = StandardScoring::default();
+
+        assert_eq!(scoring.points(&inputs(10, 10)), 1000);
+        assert_eq!(scoring.points(&inputs(20, 10)), 500);
+        assert_eq!(
+            scoring.points(&ScoreInputs {
+                blocked_moves: 2,
+                tiles_revealed: 30,
+                probe_cost: 5,
+                elapsed: Duration::from_millis(4500),
+                ..inputs(10, 10)
+            }),
+            1000 - 20 - 30 - 4 - 5
+        );
+        assert_eq!(
+            scoring.points(&ScoreInputs {
+                blocked_moves: 1000,
+                ..inputs(10, 10)
+            }),
+            0
+        );
+        assert_eq!(
+            scoring.points(&ScoreInputs {
+                blocked_moves: usize::MAX,
+                probe_cost: usize::MAX,
+                ..inputs(10, 10)
+            }),
+            0
+        );
+    }
+
+    #[test]
+    /// The speed formula only cares about time once the game took longer than par.
+    fn speed_scoring() {
+        let scoring = SpeedScoring::default();
+
+        let fast = ScoreInputs {
+            elapsed: Duration::from_secs(30),
+            ..inputs(50, 10)
+        };
+        let slow = ScoreInputs {
+            elapsed: Duration::from_secs(240),
+            blocked_moves: 3,
+            ..inputs(10, 10)
+        };
+
+        assert_eq!(scoring.points(&fast), 1000);
+        assert_eq!(scoring.points(&slow), 250 - 30);
+    }
+
+    #[test]
+    /// A score serializes flat with the elapsed time in milliseconds.
+    fn score_serializes_flat() {
+        let score = ScoreInputs {
+            elapsed: Duration::from_millis(1234),
+            ..inputs(12, 10)
+        }
+        .score(&StandardScoring::default());
+
+        assert_eq!(
+            serde_json::to_string(&score).unwrap(),
+            r#"{"points":832,"moves":12,"optimal_moves":10,"blocked_moves":0,"tiles_revealed":0,"probe_cost":0,"elapsed_ms":1234}"#
+        );
+    }
+}
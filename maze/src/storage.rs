@@ -0,0 +1,206 @@
+use super::{Tile, TileType, TileVisibility};
+
+/// The backing store for the tiles of a maze. `Dense` keeps one `Tile` per cell while `Compact`
+/// packs the walls and the visibility of every cell into two bitsets, which takes an eighth of the
+/// memory and lets very large mazes fit into the wasm heap.
+#[derive(Debug, Clone)]
+pub(crate) enum TileMap {
+    Dense(Vec<Tile>),
+    Compact(CompactTiles),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CompactTiles {
+    len: usize,
+    blocked: BitSet,
+    revealed: BitSet,
+}
+
+#[derive(Debug, Clone)]
+struct BitSet(Vec<u64>);
+
+impl TileMap {
+    pub fn dense(tiles: impl IntoIterator<Item = Tile>) -> Self {
+        TileMap::Dense(tiles.into_iter().collect())
+    }
+
+    /// A map of `len` hidden and blocked tiles, for a generator to open the passages of a maze in.
+    pub fn blocked(len: usize, compact: bool) -> Self {
+        if compact {
+            TileMap::Compact(CompactTiles {
+                len,
+                blocked: BitSet::filled(len),
+                revealed: BitSet::new(len),
+            })
+        } else {
+            TileMap::Dense(vec![Tile::blocked(); len])
+        }
+    }
+
+    pub fn compact(tiles: impl ExactSizeIterator<Item = Tile>) -> Self {
+        let len = tiles.len();
+        let mut blocked = BitSet::new(len);
+        let mut revealed = BitSet::new(len);
+
+        for (i, tile) in tiles.enumerate() {
+            blocked.set(i, tile.tile_type == TileType::Blocked);
+            revealed.set(i, tile.is_revealed());
+        }
+
+        TileMap::Compact(CompactTiles {
+            len,
+            blocked,
+            revealed,
+        })
+    }
+
+    pub fn is_compact(&self) -> bool {
+        match self {
+            TileMap::Dense(_) => false,
+            TileMap::Compact(_) => true,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            TileMap::Dense(tiles) => tiles.len(),
+            TileMap::Compact(tiles) => tiles.len,
+        }
+    }
+
+    pub fn get(&self, i: usize) -> Tile {
+        match self {
+            TileMap::Dense(tiles) => tiles[i],
+            TileMap::Compact(tiles) => {
+                assert!(i < tiles.len, "tile index out of bounds");
+                Tile {
+                    tile_type: if tiles.blocked.get(i) {
+                        TileType::Blocked
+                    } else {
+                        TileType::Open
+                    },
+                    visibility: if tiles.revealed.get(i) {
+                        TileVisibility::Revealed
+                    } else {
+                        TileVisibility::Hidden
+                    },
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub fn set(&mut self, i: usize, tile: Tile) {
+        match self {
+            TileMap::Dense(tiles) => tiles[i] = tile,
+            TileMap::Compact(tiles) => {
+                assert!(i < tiles.len, "tile index out of bounds");
+                tiles.blocked.set(i, tile.tile_type == TileType::Blocked);
+                tiles.revealed.set(i, tile.is_revealed());
+            }
+        }
+    }
+
+    pub fn is_blocked(&self, i: usize) -> bool {
+        self.get(i).tile_type == TileType::Blocked
+    }
+
+    /// Opens up the tile at `i`, leaving it hidden or revealed as it was.
+    pub fn open(&mut self, i: usize) {
+        match self {
+            TileMap::Dense(tiles) => tiles[i].tile_type = TileType::Open,
+            TileMap::Compact(tiles) => {
+                assert!(i < tiles.len, "tile index out of bounds");
+                tiles.blocked.set(i, false);
+            }
+        }
+    }
+
+    /// Reveals the tile at `i`, returning whether it was hidden before.
+    pub fn reveal(&mut self, i: usize) -> bool {
+        match self {
+            TileMap::Dense(tiles) => {
+                let hidden = !tiles[i].is_revealed();
+                tiles[i].reveal();
+                hidden
+            }
+            TileMap::Compact(tiles) => {
+                assert!(i < tiles.len, "tile index out of bounds");
+                let hidden = !tiles.revealed.get(i);
+                tiles.revealed.set(i, true);
+                hidden
+            }
+        }
+    }
+}
+
+impl BitSet {
+    fn new(bits: usize) -> Self {
+        BitSet(vec![0; bits.div_ceil(64)])
+    }
+
+    /// A bitset of `bits` bits that are all set.
+    fn filled(bits: usize) -> Self {
+        BitSet(vec![!0; bits.div_ceil(64)])
+    }
+
+    fn get(&self, i: usize) -> bool {
+        self.0[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn set(&mut self, i: usize, value: bool) {
+        if value {
+            self.0[i / 64] |= 1 << (i % 64);
+        } else {
+            self.0[i / 64] &= !(1 << (i % 64));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiles() -> Vec<Tile> {
+        let mut revealed = Tile::blocked();
+        revealed.reveal();
+        (0..130)
+            .map(|i| match i % 3 {
+                0 => Tile::open(),
+                1 => Tile::blocked(),
+                _ => revealed,
+            })
+            .collect()
+    }
+
+    #[test]
+    /// The compact representation returns the same tiles it was built from, including across the
+    /// boundaries of the words backing the bitsets.
+    fn compact_tiles_match_dense_tiles() {
+        let dense = TileMap::dense(tiles());
+        let compact = TileMap::compact(tiles().into_iter());
+
+        assert_eq!(compact.len(), dense.len());
+        for i in 0..dense.len() {
+            assert_eq!(compact.get(i), dense.get(i));
+        }
+    }
+
+    #[test]
+    /// Revealing or replacing a tile in the compact representation only changes that tile.
+    fn compact_tiles_reveal_and_set_single_tile() {
+        let mut dense = TileMap::dense(tiles());
+        let mut compact = TileMap::compact(tiles().into_iter());
+
+        for &i in &[0, 63, 64, 129] {
+            dense.reveal(i);
+            compact.reveal(i);
+        }
+        dense.set(65, Tile::open());
+        compact.set(65, Tile::open());
+
+        for i in 0..dense.len() {
+            assert_eq!(compact.get(i), dense.get(i));
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use super::storage::TileMap;
+use super::{CornerCutting, GameStats, Maze, Movement, Position, Tile, TileType};
+#[cfg(test)]
+use super::{Generator, Visibility};
+use derive_more::Display;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// A broken invariant that makes a maze unplayable.
+#[derive(Debug, Display, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case", tag = "problem")]
+pub enum Problem {
+    #[display(fmt = "the maze has no tiles")]
+    Empty,
+    #[display(fmt = "the map has {} tiles but the maze needs {}", actual, expected)]
+    WrongTileCount { expected: usize, actual: usize },
+    #[display(fmt = "the start tile is blocked")]
+    StartBlocked,
+    #[display(fmt = "the exit tile is blocked")]
+    ExitBlocked,
+    #[display(fmt = "the exit cannot be reached from the start")]
+    ExitUnreachable,
+}
+
+/// The outcome of `Maze::validate`. Besides the problems that make a maze unplayable it describes
+/// the shape of the open tiles, which tells hand-built levels apart from the perfect mazes made by
+/// the generator.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationReport {
+    pub problems: Vec<Problem>,
+    pub open_tiles: usize,
+    /// The number of groups of open tiles that are connected to each other but to no other open
+    /// tile, moving only up, down, left and right.
+    pub regions: usize,
+    /// Open tiles that cannot be reached from the start.
+    pub isolated_tiles: usize,
+    /// The number of independent loops in the open tiles, so zero if there is only ever one path
+    /// between any two tiles.
+    pub cycles: usize,
+}
+
+impl ValidationReport {
+    /// Whether the maze can be played, i.e. whether no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Whether the maze is valid and its open tiles form a single spanning tree, as every generated
+    /// maze does.
+    pub fn is_perfect(&self) -> bool {
+        self.is_valid() && self.regions == 1 && self.cycles == 0
+    }
+}
+
+impl Maze {
+    /// Builds a maze from hand-made tiles, listed row by row, with the player at the top left and
+    /// the exit at the bottom right. Returns the report of `validate` instead if the maze cannot be
+    /// played.
+    pub fn from_tiles(
+        width: usize,
+        height: usize,
+        tiles: Vec<Tile>,
+    ) -> Result<Maze, ValidationReport> {
+        let mut maze = Maze {
+            player: Position { x: 0, y: 0 },
+            exit: Position {
+                x: width.saturating_sub(1),
+                y: height.saturating_sub(1),
+            },
+            width,
+            height,
+            map: TileMap::dense(tiles),
+            movement: Movement::default(),
+            corner_cutting: CornerCutting::default(),
+            probe_cost: 0,
+            probes: 0,
+            stats: GameStats::default(),
+        };
+
+        let report = maze.validate();
+        if !report.is_valid() {
+            return Err(report);
+        }
+
+        maze.reveal_around_player();
+        maze.stats = GameStats::default();
+        Ok(maze)
+    }
+
+    /// Checks the structural invariants of the maze: that the map has a tile for every position,
+    /// that the start and exit are open and that the exit can be reached from the start.
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport {
+            problems: Vec::new(),
+            open_tiles: 0,
+            regions: 0,
+            isolated_tiles: 0,
+            cycles: 0,
+        };
+
+        let expected = self.width * self.height;
+        if expected == 0 {
+            report.problems.push(Problem::Empty);
+            return report;
+        }
+        if self.map.len() != expected {
+            report.problems.push(Problem::WrongTileCount {
+                expected,
+                actual: self.map.len(),
+            });
+            return report;
+        }
+
+        let open = |x: usize, y: usize| self.tile_at(x, y).tile_type == TileType::Open;
+        let start_open = open(0, 0);
+        if !start_open {
+            report.problems.push(Problem::StartBlocked);
+        }
+        if !open(self.exit.x, self.exit.y) {
+            report.problems.push(Problem::ExitBlocked);
+        }
+        if report.is_valid() && self.optimal_moves().is_none() {
+            report.problems.push(Problem::ExitUnreachable);
+        }
+
+        // Flood fill each region in turn, starting with the region of the start so that every
+        // tile outside of the first region is isolated from it.
+        let mut visited = vec![false; expected];
+        let mut edges = 0;
+        let starts = (0..self.height).flat_map(|y| (0..self.width).map(move |x| (x, y)));
+        for (x, y) in starts {
+            if !open(x, y) || visited[self.to_index(x, y)] {
+                continue;
+            }
+
+            let region = report.regions;
+            report.regions += 1;
+            visited[self.to_index(x, y)] = true;
+            let mut queue = VecDeque::new();
+            queue.push_back((x, y));
+
+            while let Some((x, y)) = queue.pop_front() {
+                report.open_tiles += 1;
+                if !start_open || region > 0 {
+                    report.isolated_tiles += 1;
+                }
+
+                // Count each edge from the tile on its left or top only.
+                if x + 1 < self.width && open(x + 1, y) {
+                    edges += 1;
+                }
+                if y + 1 < self.height && open(x, y + 1) {
+                    edges += 1;
+                }
+
+                let neighbours = [
+                    (x.wrapping_sub(1), y),
+                    (x + 1, y),
+                    (x, y.wrapping_sub(1)),
+                    (x, y + 1),
+                ];
+                for &(nx, ny) in neighbours.iter() {
+                    if nx < self.width && ny < self.height && open(nx, ny) {
+                        let i = self.to_index(nx, ny);
+                        if !visited[i] {
+                            visited[i] = true;
+                            queue.push_back((nx, ny));
+                        }
+                    }
+                }
+            }
+        }
+
+        // A forest with these tiles and regions has exactly `open_tiles - regions` edges, every
+        // extra edge closes a loop.
+        report.cycles = edges + report.regions - report.open_tiles;
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Builds the tiles of a maze from rows of `#` for blocked and `.` for open tiles.
+    fn tiles(rows: &[&str]) -> Vec<Tile> {
+        rows.iter()
+            .flat_map(|row| row.bytes())
+            .map(|tile| match tile {
+                b'#' => Tile::blocked(),
+                _ => Tile::open(),
+            })
+            .collect()
+    }
+
+    #[test]
+    /// Every generated maze is a perfect maze, whatever the seed.
+    fn generated_mazes_are_perfect() {
+        for seed in 0..2000 {
+            let size = 1 + 2 * (seed as usize % 20);
+            let mut rng = StdRng::seed_from_u64(seed);
+            let compact = seed % 2 == 0;
+            let map = Generator::Kruskal.generate(size, size, compact, &mut rng);
+            let maze = Maze::with_map(size, size, map, Visibility::Fog);
+
+            let report = maze.validate();
+            assert!(report.is_perfect(), "seed {}: {:?}", seed, report);
+            assert_eq!(report.isolated_tiles, 0);
+        }
+    }
+
+    #[test]
+    /// Hand-built mazes may contain loops and isolated rooms, but only an exit that cannot be
+    /// reached makes them invalid.
+    fn hand_built_maze_with_loops() {
+        let report = Maze::from_tiles(5, 5, tiles(&["...#.", ".#.#.", "...##", "####.", "....."]))
+            .unwrap_err();
+
+        assert_eq!(report.problems, vec![Problem::ExitUnreachable]);
+        assert_eq!(report.regions, 3);
+        assert_eq!(report.isolated_tiles, 8);
+        assert_eq!(report.cycles, 1);
+
+        let maze = Maze::from_tiles(3, 3, tiles(&["...", ".#.", "..."])).unwrap();
+        let report = maze.validate();
+
+        assert!(report.is_valid());
+        assert!(!report.is_perfect());
+        assert_eq!(report.cycles, 1);
+        assert!(maze.tile_at(1, 0).is_revealed());
+        assert_eq!(maze.stats(), GameStats::default());
+    }
+
+    #[test]
+    /// Each broken invariant is reported.
+    fn problems_are_reported() {
+        let cases = vec![
+            (0, vec![], vec![Problem::Empty]),
+            (
+                2,
+                tiles(&["..."]),
+                vec![Problem::WrongTileCount {
+                    expected: 4,
+                    actual: 3,
+                }],
+            ),
+            (
+                2,
+                tiles(&["#.", ".#"]),
+                vec![Problem::StartBlocked, Problem::ExitBlocked],
+            ),
+            (2, tiles(&[".#", "#."]), vec![Problem::ExitUnreachable]),
+        ];
+
+        for (size, tiles, problems) in cases {
+            assert_eq!(
+                Maze::from_tiles(size, size, tiles).unwrap_err().problems,
+                problems
+            );
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use super::{InfiniteMaze, Maze, MazeConfig, Replay, ReplayPlayer};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+#[wasm_bindgen(typescript_custom_section)]
+const MAP_ROWS: &str = r#"
+/** The name of a single tile of a map, as returned by the `/map` endpoint. */
+export type MapTileName = "hidden" | "open" | "blocked" | "player" | "exit";
+
+/** A map as a list of rows, as returned by the `/map` endpoint. */
+export type MapRows = MapTileName[][];
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "MapRows")]
+    pub type MapRows;
+}
+
+#[wasm_bindgen(start)]
+pub fn start() {
+    console_error_panic_hook::set_once();
+}
+
+#[wasm_bindgen]
+impl Maze {
+    /// Generates a maze from a `MazeConfig` object, the same options the `/start` endpoint takes.
+    pub fn from_config(config: JsValue) -> Result<Maze, JsValue> {
+        let config: MazeConfig = serde_wasm_bindgen::from_value(config)?;
+        config
+            .generate()
+            .map_err(|error| JsValue::from_str(&error.to_string()))
+    }
+
+    /// The map as the same 2d array of tile names the `/map` endpoint returns.
+    pub fn map(&self) -> Result<MapRows, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(self)?.unchecked_into())
+    }
+
+    /// The neighbouring tile types as the same object the `/move` endpoints return.
+    pub fn neighbours(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.neighbouring_tile_types()).map_err(Into::into)
+    }
+}
+
+#[wasm_bindgen]
+impl InfiniteMaze {
+    /// The `(2 * radius + 1)` tiles wide square around the player as a 2d array of tile names.
+    pub fn map(&self, radius: usize) -> Result<MapRows, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.window(radius))?.unchecked_into())
+    }
+
+    /// The neighbouring tile types as the same object the `/move` endpoints return.
+    pub fn neighbours(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.neighbouring_tile_types()).map_err(Into::into)
+    }
+}
+
+#[wasm_bindgen]
+impl ReplayPlayer {
+    /// Loads a replay from the object the `/replay` endpoint returns.
+    pub fn from_replay(replay: JsValue) -> Result<ReplayPlayer, JsValue> {
+        let replay: Replay = serde_wasm_bindgen::from_value(replay)?;
+        ReplayPlayer::new(replay).map_err(|error| JsValue::from_str(&error.to_string()))
+    }
+
+    /// Moves to the state after the first `turn` turns.
+    #[wasm_bindgen(js_name = seek)]
+    pub fn seek_turn(&mut self, turn: usize) -> Result<(), JsValue> {
+        self.seek(turn)
+            .map_err(|error| JsValue::from_str(&error.to_string()))
+    }
+
+    /// The number of turns in the replay.
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.turns().len()
+    }
+
+    /// The map at the current turn as the same 2d array of tile names the `/map` endpoint returns.
+    pub fn map(&self) -> Result<MapRows, JsValue> {
+        self.maze().map()
+    }
+}
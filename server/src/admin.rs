@@ -0,0 +1,116 @@
+use crate::api::{check_name, Account, SledStorage, Storage, ANONYMOUS};
+use crate::config::{Command, Config, PlayerCommand};
+use std::io;
+
+/// Runs `command` against the session database of `config` and prints what it did.
+pub fn run(command: &Command, config: &Config) -> io::Result<()> {
+    let path = config.session_db.as_ref().ok_or_else(|| {
+        invalid("players are saved in the session database, so `session_db` must be set")
+    })?;
+    // The server holds the lock of the database while it runs, and only reads the players when it
+    // starts, so they can only be changed while it is stopped.
+    let mut storage = SledStorage::open(path).map_err(|error| {
+        io::Error::new(
+            error.kind(),
+            format!(
+                "{}: {}, players can only be managed while the server is stopped",
+                path.display(),
+                error
+            ),
+        )
+    })?;
+    let output = match command {
+        Command::Player(command) => player(command, &mut storage)?,
+    };
+    storage.flush()?;
+    print!("{}", output);
+    Ok(())
+}
+
+/// Runs a `player` command against `storage` and returns what it prints.
+fn player(command: &PlayerCommand, storage: &mut dyn Storage) -> io::Result<String> {
+    let accounts = storage.load_accounts()?;
+    let find = |name: &str| {
+        accounts
+            .iter()
+            .find(|account| account.name == name)
+            .cloned()
+            .ok_or_else(|| invalid(&format!("{} is not registered", name)))
+    };
+
+    match command {
+        PlayerCommand::Add { name } => {
+            check_name(name).map_err(|error| invalid(&error))?;
+            if name == ANONYMOUS || find(name).is_ok() {
+                return Err(invalid(&format!("{} cannot be registered", name)));
+            }
+            let (account, key) = Account::new(name.clone());
+            storage.save_account(&account)?;
+            Ok(format!("Registered {} with the API key\n{}\n", name, key))
+        }
+        PlayerCommand::RotateKey { name } => {
+            let mut account = find(name)?;
+            let key = account.rotate_key();
+            storage.save_account(&account)?;
+            Ok(format!("The new API key of {} is\n{}\n", name, key))
+        }
+        PlayerCommand::Remove { name } => {
+            find(name)?;
+            storage.remove_account(name)?;
+            Ok(format!("Removed {}\n", name))
+        }
+        PlayerCommand::List => Ok(accounts
+            .iter()
+            .map(|account| format!("{}\n", account.name))
+            .collect()),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::MemoryStorage;
+
+    fn execute(command: PlayerCommand, storage: &mut MemoryStorage) -> io::Result<String> {
+        player(&command, storage)
+    }
+
+    #[test]
+    /// Players are registered once with a key that can be replaced, and can be removed again
+    fn players_managed() {
+        let mut storage = MemoryStorage::default();
+        let add = || PlayerCommand::Add {
+            name: "ada".to_string(),
+        };
+
+        let added = execute(add(), &mut storage).unwrap();
+        let hash = storage.load_accounts().unwrap()[0].key_hash.clone();
+        let again = execute(add(), &mut storage);
+        execute(
+            PlayerCommand::RotateKey {
+                name: "ada".to_string(),
+            },
+            &mut storage,
+        )
+        .unwrap();
+        let rotated = storage.load_accounts().unwrap()[0].key_hash.clone();
+        let listed = execute(PlayerCommand::List, &mut storage).unwrap();
+        execute(
+            PlayerCommand::Remove {
+                name: "ada".to_string(),
+            },
+            &mut storage,
+        )
+        .unwrap();
+
+        assert!(added.starts_with("Registered ada"));
+        assert!(again.is_err());
+        assert_ne!(hash, rotated);
+        assert_eq!(listed, "ada\n");
+        assert!(storage.load_accounts().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+use crate::error::ServiceError;
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The longest name a player can give.
+const MAX_PLAYER_LENGTH: usize = 32;
+
+/// A registered player. Only the hash of their API key is kept, the key itself is shown once when
+/// it is created.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Account {
+    pub name: String,
+    /// The SHA-256 hash of the API key of the player, in hex.
+    pub key_hash: String,
+    /// When the player was registered, in seconds since the unix epoch.
+    pub created_at: u64,
+}
+
+/// The registered players, looked up by their API key. Games started with a key are played under
+/// the name of its player, and the names of registered players cannot be used without their key.
+#[derive(Debug, Default)]
+pub struct Accounts {
+    /// The name of the player of each key hash.
+    players: HashMap<String, String>,
+}
+
+/// The API key sent in the X-API-KEY header, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiKey(pub Option<String>);
+
+impl Account {
+    /// Registers the player `name` and returns their account with the new API key.
+    pub fn new(name: String) -> (Self, String) {
+        let mut account = Account {
+            name,
+            key_hash: String::new(),
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_secs())
+                .unwrap_or(0),
+        };
+        let key = account.rotate_key();
+        (account, key)
+    }
+
+    /// Replaces the API key of the player, so the old one stops working, and returns the new key.
+    pub fn rotate_key(&mut self) -> String {
+        let key: String = rand::random::<[u8; 32]>()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        self.key_hash = hash_key(&key);
+        key
+    }
+}
+
+impl Accounts {
+    pub fn new(accounts: Vec<Account>) -> Self {
+        Accounts {
+            players: accounts
+                .into_iter()
+                .map(|account| (account.key_hash, account.name))
+                .collect(),
+        }
+    }
+
+    /// The name of the player `key` belongs to.
+    pub fn authenticate(&self, key: &str) -> Option<&str> {
+        self.players.get(&hash_key(key)).map(String::as_str)
+    }
+
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.players.values().any(|player| player == name)
+    }
+}
+
+/// Checks that `name` can be used as the name of a player.
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.chars().count() > MAX_PLAYER_LENGTH {
+        return Err(format!(
+            "the player must be a name of 1 to {} characters",
+            MAX_PLAYER_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+impl FromRequest for ApiKey {
+    type Error = ServiceError;
+    type Future = Result<Self, ServiceError>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _pl: &mut Payload) -> Self::Future {
+        match req.headers().get("x-api-key") {
+            Some(key) => Ok(ApiKey(Some(
+                key.to_str()
+                    .map_err(|_| ServiceError::InvalidApiKey)?
+                    .to_string(),
+            ))),
+            None => Ok(ApiKey(None)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Keys find their own player only, and are not kept in the account themselves
+    fn keys_authenticate_their_player() {
+        let (ada, ada_key) = Account::new("ada".to_string());
+        let (mut grace, old_key) = Account::new("grace".to_string());
+        let grace_key = grace.rotate_key();
+        let accounts = Accounts::new(vec![ada.clone(), grace]);
+
+        assert_eq!(accounts.authenticate(&ada_key), Some("ada"));
+        assert_eq!(accounts.authenticate(&grace_key), Some("grace"));
+        assert_eq!(accounts.authenticate(&old_key), None);
+        assert_eq!(accounts.authenticate("guess"), None);
+        assert_ne!(ada.key_hash, ada_key);
+        assert!(accounts.is_registered("grace"));
+        assert!(!accounts.is_registered("anonymous"));
+    }
+}
@@ -0,0 +1,218 @@
+use super::accounts::ApiKey;
+use super::start::{begin, default_options, identify, merge, read_options};
+use super::{GameDefaults, Sessions};
+use crate::error::ServiceError;
+use crate::game::NewGame;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The secret the seeds of the daily challenge are derived from, so they cannot be worked out
+/// ahead of time from the date.
+pub struct Secret(pub String);
+
+pub type DailySecret = web::Data<Secret>;
+
+/// A session playing the daily challenge.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DailyAttempt {
+    /// The day of the challenge in UTC, such as `2019-10-18`.
+    pub date: String,
+    /// Whether the game goes on the leaderboard of the day once won. Only the first attempt of
+    /// each named player each day is scored.
+    pub scored: bool,
+}
+
+/// A day of the daily challenge, counted in days since the unix epoch in UTC.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Day(u64);
+
+impl Day {
+    pub fn today() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or(0);
+        Day(now / SECONDS_PER_DAY)
+    }
+
+    /// The seed every game of the challenge is started from that day: the start of the hash of
+    /// `secret` and the date.
+    pub fn seed(self, secret: &str) -> u64 {
+        let hash = Sha256::new()
+            .chain(secret.as_bytes())
+            .chain(b":")
+            .chain(self.date().as_bytes())
+            .result();
+        u64::from_be_bytes(hash[..8].try_into().unwrap())
+    }
+
+    /// The date of the day, such as `2019-10-18`.
+    pub fn date(self) -> String {
+        // Counts from 1 March 0000 so that leap days fall at the end of the year, see
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let days = self.0 + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 {
+            month_from_march + 3
+        } else {
+            month_from_march - 9
+        };
+        let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+/// The /daily endpoint of every game. Starts a game of today's challenge, which is the same for
+/// everyone playing it that day: the configured defaults with the seed of the day. The body can
+/// only name the `player`, or an API key can be sent as with /start. The first attempt of each
+/// named player each day is entered on the leaderboard of the day once won, later attempts and
+/// those of anonymous players can be played but are not scored.
+pub fn daily<G: NewGame>(
+    body: String,
+    key: ApiKey,
+    defaults: Option<GameDefaults>,
+    secret: Option<DailySecret>,
+    state: Sessions,
+) -> Result<HttpResponse, ServiceError> {
+    let mut requested = read_options(&body)?;
+    let player = identify(&mut requested, &key, state.lock().unwrap().accounts())?;
+    if let Some(option) = requested
+        .as_object()
+        .and_then(|options| options.keys().next())
+    {
+        return Err(ServiceError::InvalidOptions(format!(
+            "the daily challenge is the same for everyone, so `{}` cannot be chosen",
+            option
+        )));
+    }
+
+    let day = Day::today();
+    let mut options = default_options::<G>(defaults);
+    let secret = secret.as_ref().map_or("", |secret| secret.0.as_str());
+    merge(&mut options, G::daily(day.seed(secret)))?;
+    begin::<G>(options, player, Some(day.date()), state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{routes, SessionStore};
+    use super::*;
+    use actix_web::{http::StatusCode, test, web, App};
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    #[test]
+    /// Days are turned into calendar dates, leap days included
+    fn dates_of_days() {
+        assert_eq!(Day(0).date(), "1970-01-01");
+        assert_eq!(Day(18187).date(), "2019-10-18");
+        assert_eq!(Day(18321).date(), "2020-02-29");
+        assert_eq!(Day(18322).date(), "2020-03-01");
+    }
+
+    #[test]
+    /// The seed of a day cannot be told without the secret, and changes every day
+    fn seeds_depend_on_secret() {
+        let day = Day(18187);
+
+        assert_eq!(day.seed("shh"), day.seed("shh"));
+        assert_ne!(day.seed("shh"), day.seed("psst"));
+        assert_ne!(day.seed("shh"), Day(18188).seed("shh"));
+        assert_ne!(day.seed("shh"), 18187);
+    }
+
+    #[test]
+    /// Everyone plays the same maze, but only the first attempt of each named player is scored
+    fn one_scored_attempt_per_player() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let secret: DailySecret = web::Data::new(Secret("shh".to_string()));
+        let mut app = test::init_service(
+            App::new()
+                .register_data(sessions.clone())
+                .register_data(secret)
+                .configure(routes),
+        );
+        let mut attempt = |body: Value| -> Value {
+            let req = test::TestRequest::post()
+                .uri("/game/maze/daily")
+                .set_json(&body)
+                .to_request();
+            test::read_response_json(&mut app, req)
+        };
+
+        let first = attempt(json!({ "player": "ada" }));
+        let second = attempt(json!({ "player": "ada" }));
+        let other = attempt(json!({ "player": "grace" }));
+        let anonymous = attempt(json!({}));
+
+        let today = Day::today();
+        let token = serde_json::from_value(first["token"].clone()).unwrap();
+        let config = sessions
+            .lock()
+            .unwrap()
+            .get(&token)
+            .unwrap()
+            .game()
+            .config();
+        assert_eq!(config["seed"], today.seed("shh"));
+        assert_eq!(second["config"], first["config"]);
+        assert_eq!(
+            first["daily"],
+            json!({ "date": today.date(), "scored": true })
+        );
+        assert_eq!(second["daily"]["scored"], false);
+        assert_eq!(other["daily"]["scored"], true);
+        assert_eq!(anonymous["daily"]["scored"], false);
+    }
+
+    #[test]
+    /// The seed of today's challenge is not given away by the start response or the replay, so
+    /// the maze cannot be worked out ahead of a scored attempt
+    fn seed_kept_secret() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::post()
+            .uri("/game/maze/daily")
+            .set_json(&json!({}))
+            .to_request();
+        let started: Value = test::read_response_json(&mut app, req);
+        let req = test::TestRequest::get()
+            .uri("/game/maze/replay")
+            .header("X-TOKEN", started["token"].as_str().unwrap())
+            .to_request();
+        let replay: Value = test::read_response_json(&mut app, req);
+
+        assert!(started["config"].get("width").is_some());
+        assert!(started["config"].get("seed").is_none());
+        assert!(replay["config"].get("width").is_some());
+        assert!(replay["config"].get("seed").is_none());
+    }
+
+    #[test]
+    /// The options of the challenge cannot be changed
+    fn options_rejected() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let req = test::TestRequest::post()
+            .uri("/game/maze/daily")
+            .set_json(&json!({ "player": "ada", "seed": 1 }))
+            .to_request();
+
+        let response = test::call_service(&mut app, req);
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(sessions.lock().unwrap().is_empty());
+    }
+}
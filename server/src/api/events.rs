@@ -0,0 +1,123 @@
+use super::{SessionToken, Sessions};
+use crate::error::ServiceError;
+use crate::game::Event;
+use actix_web::{error, http::header, web::Bytes, HttpResponse};
+use futures::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::Stream;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Passes the events of every session on to the clients following it on /events. Events are
+/// published by the `SessionStore` whenever a session changes, so games only need to report them
+/// with `Game::take_events`.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: HashMap<SessionToken, Vec<UnboundedSender<Bytes>>>,
+}
+
+impl EventBus {
+    /// Starts following the session of `token`, sending `first` to the new follower only.
+    pub fn subscribe(&mut self, token: SessionToken, first: &Event) -> UnboundedReceiver<Bytes> {
+        let (sender, receiver) = mpsc::unbounded();
+        if sender.unbounded_send(server_sent_event(first)).is_ok() {
+            self.subscribers.entry(token).or_default().push(sender);
+        }
+        receiver
+    }
+
+    /// Sends `event` to everyone following the session of `token`, forgetting the followers that
+    /// have disconnected.
+    pub fn publish(&mut self, token: SessionToken, event: &Event) {
+        if let Some(subscribers) = self.subscribers.get_mut(&token) {
+            let message = server_sent_event(event);
+            subscribers.retain(|subscriber| subscriber.unbounded_send(message.clone()).is_ok());
+        }
+    }
+
+    /// Ends the streams of everyone following the session of `token`.
+    pub fn close(&mut self, token: &SessionToken) {
+        self.subscribers.remove(token);
+    }
+}
+
+/// Formats `event` as a server-sent event named after its type, with the event as JSON for data.
+fn server_sent_event(event: &Event) -> Bytes {
+    let data = json!(event);
+    let name = data["type"].as_str().unwrap_or("message");
+    Bytes::from(format!("event: {}\ndata: {}\n\n", name, data))
+}
+
+/// The /events endpoint of every game. Streams the events of the session as server-sent events
+/// until the session expires. Spectators can follow the session with their token too.
+pub fn events(state: Sessions, token: SessionToken) -> Result<HttpResponse, ServiceError> {
+    let receiver = state.lock().unwrap().subscribe(&token)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .streaming(receiver.map_err(|()| error::ErrorInternalServerError("event stream failed"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Session, SessionStore};
+    use super::*;
+    use crate::games::maze::MazeGame;
+    use crate::maze::{GameMode, Maze};
+
+    fn messages(receiver: UnboundedReceiver<Bytes>, count: usize) -> Vec<String> {
+        receiver
+            .wait()
+            .take(count)
+            .map(|message| String::from_utf8(message.unwrap().to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    /// Events are sent to the followers of their session only, as named server-sent events
+    fn events_reach_followers_of_session() {
+        let mut bus = EventBus::default();
+        let (followed, other) = (SessionToken::new(), SessionToken::new());
+        let receiver = bus.subscribe(followed, &Event::Revealed { tiles: 2 });
+        let other_receiver = bus.subscribe(other, &Event::Revealed { tiles: 3 });
+
+        bus.publish(followed, &Event::Expired);
+        bus.close(&followed);
+        bus.close(&other);
+
+        assert_eq!(
+            receiver
+                .wait()
+                .map(|message| message.unwrap())
+                .collect::<Vec<_>>(),
+            vec![
+                Bytes::from("event: revealed\ndata: {\"tiles\":2,\"type\":\"revealed\"}\n\n"),
+                Bytes::from("event: expired\ndata: {\"type\":\"expired\"}\n\n"),
+            ]
+        );
+        assert_eq!(other_receiver.wait().count(), 1);
+    }
+
+    #[test]
+    /// Playing a session through the store publishes the events of the game, and spectators can
+    /// follow them
+    fn changes_to_session_published() {
+        let mut store = SessionStore::default();
+        let token = SessionToken::new();
+        let session = Session::new(MazeGame::new(Maze::new(9), GameMode::Standard));
+        let spectator = session.spectator();
+        store.insert(token, session).unwrap();
+        let receiver = store.subscribe(&spectator).unwrap();
+
+        store
+            .get_mut(&token)
+            .unwrap()
+            .game_mut()
+            .act(json!({ "moves": ["up"] }))
+            .unwrap();
+
+        let messages = messages(receiver, 2);
+        assert!(messages[0].starts_with("event: started\n"));
+        assert!(messages[1].starts_with("event: blocked\n"));
+        assert!(messages[1].contains(r#""direction":"up""#));
+    }
+}
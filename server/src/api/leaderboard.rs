@@ -0,0 +1,480 @@
+use super::daily::Day;
+use super::tournament::{TournamentId, TournamentRound};
+use super::{hide_seed, Session, Sessions};
+use crate::maze::Score;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many entries a page of the leaderboard has if the query does not say.
+const DEFAULT_PER_PAGE: usize = 20;
+
+/// The most entries a single page of the leaderboard can have.
+const MAX_PER_PAGE: usize = 100;
+
+/// The most entries kept on the leaderboard of each game, and on that of each day of the daily
+/// challenge. Worse entries drop off as better ones are added.
+const MAX_ENTRIES: usize = 1000;
+
+/// A finished game on the leaderboard.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LeaderboardEntry {
+    pub player: String,
+    /// The name of the game, see `NewGame::NAME`.
+    pub game: String,
+    /// The settings the game was started with.
+    pub config: Value,
+    pub moves: usize,
+    pub optimal_moves: usize,
+    pub points: u64,
+    /// How long it took to reach the exit.
+    pub duration_ms: u64,
+    /// When the game was won, in seconds since the unix epoch.
+    pub finished_at: u64,
+    /// The date of the daily challenge the game was played for, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily: Option<String>,
+    /// The round of the tournament the game was played in, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tournament: Option<TournamentRound>,
+}
+
+/// Every finished game, best first. Entries are added as games are won and saved to the storage
+/// of the sessions along with them, and removed from it again when they drop off the leaderboard.
+#[derive(Debug, Default)]
+pub struct Leaderboard {
+    entries: Vec<LeaderboardEntry>,
+    unsaved: Vec<LeaderboardEntry>,
+    dropped: Vec<LeaderboardEntry>,
+}
+
+/// The query of /leaderboard, such as `?game=maze&mode=speed&width=15&height=15&page=2`. Every
+/// filter may be left out, and pages are counted from 1. Games of the daily challenge are kept on
+/// a leaderboard of their own for each day, selected with `daily` as in `?daily=2019-10-18` or
+/// `?daily=today`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Query {
+    game: Option<String>,
+    mode: Option<String>,
+    width: Option<usize>,
+    height: Option<usize>,
+    daily: Option<String>,
+    page: usize,
+    per_page: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Page {
+    /// How many entries match the query, on every page.
+    total: usize,
+    page: usize,
+    per_page: usize,
+    entries: Vec<RankedEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RankedEntry {
+    /// The place of the entry among those matching the query, counted from 1.
+    rank: usize,
+    #[serde(flatten)]
+    entry: LeaderboardEntry,
+}
+
+impl LeaderboardEntry {
+    /// The entry of the game of `session`, which was just won with `score`.
+    pub fn new(session: &Session, score: &Score) -> Self {
+        LeaderboardEntry {
+            player: session.player.clone(),
+            game: session.name.to_string(),
+            config: session.game().config(),
+            moves: score.inputs.moves,
+            optimal_moves: score.inputs.optimal_moves,
+            points: score.points,
+            duration_ms: score.inputs.elapsed.as_millis() as u64,
+            finished_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_secs())
+                .unwrap_or(0),
+            daily: session.daily.as_ref().map(|daily| daily.date.clone()),
+            tournament: session.tournament,
+        }
+    }
+
+    /// Whether the entry ranks above `other`: more points first, then the faster game, then the
+    /// one finished first.
+    fn ranks_above(&self, other: &LeaderboardEntry) -> bool {
+        (other.points, self.duration_ms, self.finished_at)
+            < (self.points, other.duration_ms, other.finished_at)
+    }
+
+    /// Whether the entry is on the same leaderboard as `other`, which holds at most `MAX_ENTRIES`.
+    /// Games of tournaments are not on any, as they make up its standings.
+    fn shares_board(&self, other: &LeaderboardEntry) -> bool {
+        self.game == other.game
+            && self.daily == other.daily
+            && self.tournament.is_none()
+            && other.tournament.is_none()
+    }
+
+    fn matches(&self, query: &Query) -> bool {
+        let setting_is = |setting: &str, value: Option<Value>| {
+            value.map_or(true, |value| self.config.get(setting) == Some(&value))
+        };
+        query.game.as_ref().map_or(true, |game| &self.game == game)
+            && setting_is("mode", query.mode.clone().map(Value::from))
+            && setting_is("width", query.width.map(Value::from))
+            && setting_is("height", query.height.map(Value::from))
+            && self.daily == query.daily
+            && self.tournament.is_none()
+    }
+}
+
+impl Leaderboard {
+    /// A leaderboard of the entries loaded from storage, which are not saved again.
+    pub fn new(entries: Vec<LeaderboardEntry>) -> Self {
+        let mut leaderboard = Leaderboard::default();
+        for entry in entries {
+            leaderboard.insert(entry);
+        }
+        leaderboard
+    }
+
+    /// Adds the entry of a game that was just won, to be saved by the next flush.
+    pub fn add(&mut self, entry: LeaderboardEntry) {
+        self.unsaved.push(entry.clone());
+        self.insert(entry);
+    }
+
+    /// The entries of the games played in the tournament `id`, best first. They are not on any page
+    /// of the leaderboard, but make up the standings of the tournament.
+    pub fn tournament_results(&self, id: TournamentId) -> impl Iterator<Item = &LeaderboardEntry> {
+        self.entries.iter().filter(move |entry| {
+            entry
+                .tournament
+                .map_or(false, |round| round.tournament == id)
+        })
+    }
+
+    /// Takes the entries added since they were last taken.
+    pub fn take_unsaved(&mut self) -> Vec<LeaderboardEntry> {
+        std::mem::take(&mut self.unsaved)
+    }
+
+    /// Takes the saved entries that dropped off the leaderboard since they were last taken.
+    pub fn take_dropped(&mut self) -> Vec<LeaderboardEntry> {
+        std::mem::take(&mut self.dropped)
+    }
+
+    fn insert(&mut self, entry: LeaderboardEntry) {
+        let place = self
+            .entries
+            .iter()
+            .position(|other| entry.ranks_above(other))
+            .unwrap_or_else(|| self.entries.len());
+        self.entries.insert(place, entry);
+
+        let entry = &self.entries[place];
+        if entry.tournament.is_some() {
+            return;
+        }
+        let mut board = (0..self.entries.len()).filter(|&i| self.entries[i].shares_board(entry));
+        if let Some(last) = board.nth(MAX_ENTRIES) {
+            let dropped = self.entries.remove(last);
+            match self.unsaved.iter().position(|unsaved| *unsaved == dropped) {
+                Some(unsaved) => {
+                    self.unsaved.remove(unsaved);
+                }
+                None => self.dropped.push(dropped),
+            }
+        }
+    }
+
+    fn page(&self, query: &Query) -> Page {
+        let per_page = query.per_page.max(1).min(MAX_PER_PAGE);
+        let page = query.page.max(1);
+        let matching: Vec<&LeaderboardEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.matches(query))
+            .collect();
+
+        Page {
+            total: matching.len(),
+            page,
+            per_page,
+            entries: matching
+                .into_iter()
+                .enumerate()
+                .skip(page.saturating_sub(1).saturating_mul(per_page))
+                .take(per_page)
+                .map(|(place, entry)| RankedEntry {
+                    rank: place + 1,
+                    entry: entry.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Query {
+            game: None,
+            mode: None,
+            width: None,
+            height: None,
+            daily: None,
+            page: 1,
+            per_page: DEFAULT_PER_PAGE,
+        }
+    }
+}
+
+/// The /leaderboard endpoint. Returns a page of the finished games matching the query, best first.
+/// Games of today's daily challenge are listed without their seed until the day is over.
+pub fn leaderboard(query: web::Query<Query>, state: Sessions) -> HttpResponse {
+    let today = Day::today().date();
+    let mut query = query.into_inner();
+    if query.daily.as_ref().map_or(false, |daily| daily == "today") {
+        query.daily = Some(today.clone());
+    }
+    let mut page = state.lock().unwrap().leaderboard().page(&query);
+    for ranked in &mut page.entries {
+        if ranked.entry.daily.as_ref() == Some(&today) {
+            hide_seed(&mut ranked.entry.config);
+        }
+    }
+    HttpResponse::Ok().json(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{routes, Session, SessionStore, SessionToken};
+    use super::*;
+    use crate::games::maze::{solved_maze, MazeGame};
+    use crate::maze::{GameMode, Maze};
+    use actix_web::{test, App};
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    fn entry(mode: &str, width: usize, points: u64, duration_ms: u64) -> LeaderboardEntry {
+        LeaderboardEntry {
+            player: format!("{}-{}", points, duration_ms),
+            game: "maze".to_string(),
+            config: json!({ "mode": mode, "width": width, "height": width }),
+            moves: 10,
+            optimal_moves: 8,
+            points,
+            duration_ms,
+            finished_at: 0,
+            daily: None,
+            tournament: None,
+        }
+    }
+
+    fn query(query: &str) -> Page {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        {
+            let mut sessions = sessions.lock().unwrap();
+            for entry in vec![
+                entry("standard", 9, 500, 1000),
+                entry("speed", 9, 900, 1000),
+                entry("standard", 9, 800, 3000),
+                entry("standard", 15, 700, 1000),
+                entry("standard", 9, 800, 2000),
+                LeaderboardEntry {
+                    daily: Some("2019-10-18".to_string()),
+                    ..entry("standard", 9, 600, 1000)
+                },
+            ] {
+                sessions.leaderboard_mut().add(entry);
+            }
+        }
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::get()
+            .uri(&format!("/leaderboard{}", query))
+            .to_request();
+        test::read_response_json(&mut app, req)
+    }
+
+    fn players(page: &Page) -> Vec<(usize, &str)> {
+        page.entries
+            .iter()
+            .map(|ranked| (ranked.rank, ranked.entry.player.as_str()))
+            .collect()
+    }
+
+    #[test]
+    /// Entries are ranked by points, with faster games first among equal points
+    fn entries_ranked_best_first() {
+        let page = query("");
+
+        assert_eq!(page.total, 5);
+        assert_eq!(
+            players(&page),
+            vec![
+                (1, "900-1000"),
+                (2, "800-2000"),
+                (3, "800-3000"),
+                (4, "700-1000"),
+                (5, "500-1000")
+            ]
+        );
+    }
+
+    #[test]
+    /// Only entries of the requested mode and size are returned, ranked among themselves
+    fn filtered_by_mode_and_size() {
+        let page = query("?mode=standard&width=9&height=9");
+
+        assert_eq!(page.total, 3);
+        assert_eq!(
+            players(&page),
+            vec![(1, "800-2000"), (2, "800-3000"), (3, "500-1000")]
+        );
+    }
+
+    #[test]
+    /// Pages split the matching entries and keep their ranks
+    fn paginated() {
+        let page = query("?mode=standard&per_page=2&page=2");
+
+        assert_eq!((page.total, page.page, page.per_page), (4, 2, 2));
+        assert_eq!(players(&page), vec![(3, "700-1000"), (4, "500-1000")]);
+    }
+
+    #[test]
+    /// Games of the daily challenge are only on the leaderboard of their day
+    fn daily_games_kept_apart() {
+        let page = query("?daily=2019-10-18");
+
+        assert_eq!(page.total, 1);
+        assert_eq!(players(&page), vec![(1, "600-1000")]);
+        assert_eq!(query("?daily=2019-10-19").total, 0);
+    }
+
+    #[test]
+    /// The seeds of today's daily challenge are only listed once the day is over
+    fn todays_seeds_hidden() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        for date in &["2019-10-18".to_string(), Day::today().date()] {
+            sessions
+                .lock()
+                .unwrap()
+                .leaderboard_mut()
+                .add(LeaderboardEntry {
+                    config: json!({ "seed": 7 }),
+                    daily: Some(date.clone()),
+                    ..entry("standard", 9, 500, 1000)
+                });
+        }
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let mut seed = |daily: &str| {
+            let req = test::TestRequest::get()
+                .uri(&format!("/leaderboard?daily={}", daily))
+                .to_request();
+            let page: Page = test::read_response_json(&mut app, req);
+            page.entries[0].entry.config.get("seed").cloned()
+        };
+
+        assert_eq!(seed("2019-10-18"), Some(json!(7)));
+        assert_eq!(seed("today"), None);
+    }
+
+    #[test]
+    /// Pages far past the end are empty rather than overflowing
+    fn huge_pages_empty() {
+        let page = query(&format!("?page={}&per_page=100", usize::MAX));
+
+        assert_eq!(page.total, 5);
+        assert!(page.entries.is_empty());
+    }
+
+    #[test]
+    /// Each leaderboard keeps its best entries, and those that drop off after being saved are
+    /// removed from storage
+    fn worst_entries_dropped() {
+        let mut leaderboard = Leaderboard::new(vec![entry("standard", 9, 0, 1000)]);
+        for points in 1..=MAX_ENTRIES as u64 {
+            leaderboard.add(entry("standard", 9, points, 1000));
+        }
+        leaderboard.add(LeaderboardEntry {
+            daily: Some("2019-10-18".to_string()),
+            ..entry("standard", 9, 0, 1000)
+        });
+        let unsaved = leaderboard.take_unsaved();
+        leaderboard.add(entry("standard", 9, 0, 1000));
+
+        assert_eq!(leaderboard.entries.len(), MAX_ENTRIES + 1);
+        assert_eq!(unsaved.len(), MAX_ENTRIES + 1);
+        assert_eq!(
+            leaderboard.take_dropped(),
+            vec![entry("standard", 9, 0, 1000)]
+        );
+        assert!(leaderboard.take_unsaved().is_empty());
+        assert!(leaderboard
+            .entries
+            .iter()
+            .all(|entry| entry.points > 0 || entry.daily.is_some()));
+    }
+
+    /// Plays a maze to the exit through `store` as `session`, then takes the leaderboard entries
+    /// that were added.
+    fn win(
+        store: &mut SessionStore,
+        session: impl FnOnce(MazeGame<Maze>) -> Session,
+    ) -> Vec<LeaderboardEntry> {
+        let token = SessionToken::new();
+        let (config, route) = solved_maze();
+        let maze = config.generate().unwrap();
+        let game = MazeGame::new(maze, GameMode::Standard).with_config(json!({ "width": 3 }));
+        store.insert(token, session(game)).unwrap();
+
+        for &direction in &route {
+            let mut session = store.get_mut(&token).unwrap();
+            session
+                .downcast_mut::<MazeGame<Maze>>()
+                .unwrap()
+                .move_player(direction)
+                .unwrap();
+        }
+
+        store.leaderboard_mut().take_unsaved()
+    }
+
+    #[test]
+    /// Winning a game through the store adds it to the leaderboard under the name of its player
+    fn won_games_added() {
+        let mut store = SessionStore::default();
+
+        let entries = win(&mut store, |game| {
+            Session::new(game).with_player("ada".to_string())
+        });
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].player, "ada");
+        assert_eq!(entries[0].config, json!({ "width": 3 }));
+        assert_eq!((entries[0].moves, entries[0].optimal_moves), (4, 4));
+        assert_eq!(entries[0].daily, None);
+    }
+
+    #[test]
+    /// Only the first attempt of a player at the daily challenge is added, under its date
+    fn daily_games_scored_once() {
+        let mut store = SessionStore::default();
+        let attempt = |game: MazeGame<Maze>| {
+            Session::new(game)
+                .with_player("ada".to_string())
+                .with_daily("2019-10-18".to_string())
+        };
+
+        let first = win(&mut store, attempt);
+        let second = win(&mut store, attempt);
+
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].daily, Some("2019-10-18".to_string()));
+        assert!(second.is_empty());
+    }
+}
@@ -1,66 +1,281 @@
+use crate::config::Config;
 use crate::error::ServiceError;
-use crate::maze::Maze;
+use crate::game::{Game, NewGame};
+use crate::games;
+use actix_web::http::HeaderMap;
 use actix_web::{dev::Payload, middleware::Logger, web, App, FromRequest, HttpRequest, HttpServer};
+use daily::{DailyAttempt, DailySecret};
 use derive_more::Display;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::cell::Cell;
+use std::collections::BTreeMap;
 use std::io;
-use std::net::ToSocketAddrs;
 use std::sync::Mutex;
+use std::time::Instant;
+use tournament::TournamentRound;
 
-mod map;
-mod move_player;
+pub use accounts::{check_name, Account};
+pub use rate_limit::{Limit, RateLimit, RateLimits};
+pub use storage::{MemoryStorage, SledStorage, Storage};
+pub use store::{SessionLimits, SessionStore};
+
+mod accounts;
+mod daily;
+mod events;
+mod leaderboard;
+mod play;
+mod rate_limit;
+mod socket;
 mod start;
+mod storage;
+mod store;
+mod tournament;
+
+pub type Sessions = web::Data<Mutex<SessionStore>>;
 
-type Sessions = web::Data<Mutex<HashMap<SessionToken, Session>>>;
+/// The name of players that did not give one when starting their game.
+pub const ANONYMOUS: &str = "anonymous";
+
+/// The options each game is started with unless the request overrides them, by game name.
+pub type GameDefaults = web::Data<BTreeMap<String, Value>>;
 
 pub struct Session {
-    maze: Maze,
+    game: Box<dyn Game>,
+    /// The name of the game, see `NewGame::NAME`.
+    name: &'static str,
+    /// A second token for the session that can only watch the game.
+    spectator: SessionToken,
+    /// The name the game is entered on the leaderboard under once won.
+    player: String,
+    /// The attempt at the daily challenge the session plays, if it was started from /daily.
+    daily: Option<DailyAttempt>,
+    /// The round of a tournament the session plays, if it was started by joining one.
+    tournament: Option<TournamentRound>,
+    last_active: Cell<Instant>,
 }
 
 impl Session {
-    pub fn new(size: usize) -> Self {
+    pub fn new<G: NewGame>(game: G) -> Self {
         Session {
-            maze: Maze::new(size),
+            game: Box::new(game),
+            name: G::NAME,
+            spectator: SessionToken::new(),
+            player: ANONYMOUS.to_string(),
+            daily: None,
+            tournament: None,
+            last_active: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Sets the name of the player of the session.
+    pub fn with_player(mut self, player: String) -> Self {
+        self.player = player;
+        self
+    }
+
+    /// Marks the session as an attempt at the daily challenge of `date`. The store decides whether
+    /// the attempt is scored when the session is inserted.
+    pub fn with_daily(mut self, date: String) -> Self {
+        self.daily = Some(DailyAttempt {
+            date,
+            scored: false,
+        });
+        self
+    }
+
+    pub fn with_tournament(mut self, round: TournamentRound) -> Self {
+        self.tournament = Some(round);
+        self
+    }
+
+    pub fn spectator(&self) -> SessionToken {
+        self.spectator
+    }
+
+    pub fn game(&self) -> &dyn Game {
+        self.game.as_ref()
+    }
+
+    pub fn game_mut(&mut self) -> &mut dyn Game {
+        self.game.as_mut()
+    }
+
+    /// The game of this session as the concrete game `G`. Sessions of other games are treated as
+    /// if they did not exist, as their tokens were handed out under a different /game scope.
+    pub fn downcast_mut<G: Game>(&mut self) -> Result<&mut G, ServiceError> {
+        self.game
+            .as_any_mut()
+            .downcast_mut()
+            .ok_or(ServiceError::SessionNotFound)
+    }
+
+    pub fn downcast<G: Game>(&self) -> Result<&G, ServiceError> {
+        self.game
+            .as_any()
+            .downcast_ref()
+            .ok_or(ServiceError::SessionNotFound)
+    }
+}
+
+/// The games the server hosts. Each game is mounted under `/game/{name}` with the common /start,
+/// /daily, /act, /observe, /status, /replay, /ws and /events routes plus any routes of its own.
+#[derive(Default)]
+pub struct Registry {
+    games: Vec<RegisteredGame>,
+}
+
+struct RegisteredGame {
+    name: &'static str,
+    mount: fn(&mut web::ServiceConfig),
+    start: fn(Value) -> Result<Session, ServiceError>,
+    restore: fn(Value) -> Result<Session, ServiceError>,
+    check_options: fn(Value) -> Result<(), ServiceError>,
+}
+
+impl Registry {
+    pub fn register<G: NewGame>(mut self) -> Self {
+        self.games.push(RegisteredGame {
+            name: G::NAME,
+            mount: mount::<G>,
+            start: start::session::<G>,
+            restore: restore::<G>,
+            check_options: start::check_options::<G>,
+        });
+        self
+    }
+
+    fn configure(&self, cfg: &mut web::ServiceConfig) {
+        for game in &self.games {
+            cfg.service(web::scope(&format!("/game/{}", game.name)).configure(game.mount));
         }
     }
 
-    pub fn maze(&self) -> &Maze {
-        &self.maze
+    /// Starts a session of the game called `name` with `options`, which are not merged with any
+    /// defaults.
+    pub fn start(&self, name: &str, options: Value) -> Result<Session, ServiceError> {
+        let game = self
+            .games
+            .iter()
+            .find(|game| game.name == name)
+            .ok_or_else(|| {
+                ServiceError::InvalidOptions(format!("there is no game called {}", name))
+            })?;
+        (game.start)(options)
+    }
+
+    /// Rebuilds a saved session of the game called `name`.
+    fn restore(&self, name: &str, snapshot: Value) -> Result<Session, ServiceError> {
+        let game = self
+            .games
+            .iter()
+            .find(|game| game.name == name)
+            .ok_or(ServiceError::SessionNotFound)?;
+        (game.restore)(snapshot)
+    }
+
+    /// Checks that every game in `defaults` exists and accepts its default options.
+    fn check_defaults(&self, defaults: &BTreeMap<String, Value>) -> Result<(), io::Error> {
+        for (name, options) in defaults {
+            let invalid = |error: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("default options of {}: {}", name, error),
+                )
+            };
+            let game = self
+                .games
+                .iter()
+                .find(|game| game.name == name)
+                .ok_or_else(|| invalid("no such game".to_string()))?;
+            (game.check_options)(options.clone()).map_err(|error| invalid(error.to_string()))?;
+        }
+        Ok(())
     }
+}
 
-    pub fn mut_maze(&mut self) -> &mut Maze {
-        &mut self.maze
+/// Removes the seed from the settings of a game, so they do not give the game away.
+fn hide_seed(config: &mut Value) {
+    if let Some(config) = config.as_object_mut() {
+        config.remove("seed");
     }
 }
 
+fn restore<G: NewGame>(snapshot: Value) -> Result<Session, ServiceError> {
+    Ok(Session::new(G::restore(snapshot)?))
+}
+
+/// Registers the routes for a single game.
+fn mount<G: NewGame>(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/start").route(web::post().to(start::start::<G>)));
+    cfg.service(web::resource("/daily").route(web::post().to(daily::daily::<G>)));
+    cfg.service(web::resource("/act").route(web::post().to(play::act)));
+    cfg.service(web::resource("/observe").route(web::get().to(play::observe)));
+    cfg.service(web::resource("/status").route(web::get().to(play::status)));
+    cfg.service(web::resource("/replay").route(web::get().to(play::replay)));
+    cfg.service(web::resource("/ws").route(web::get().to(socket::connect)));
+    cfg.service(web::resource("/events").route(web::get().to(events::events)));
+    G::routes(cfg);
+}
+
 /// A session token used to identify a currently running game. Users must supply this in the
-/// X-TOKEN header and can obtain it from the /start endpoint.
+/// X-TOKEN header, or the `token` query parameter where headers cannot be set, and can obtain it
+/// from the /start endpoint.
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Default, Display, Hash, Clone, Copy)]
 pub struct SessionToken(uuid::Uuid);
 
 /// Registers the routes for this API
-fn routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::resource("/map").route(web::get().to(map::map)));
-    cfg.service(web::resource("/move").route(web::get().to(move_player::neighbouring_tile_types)));
-    cfg.service(web::resource("/start").route(web::post().to(start::start)));
-    cfg.service(web::resource("/move/{direction}").route(web::post().to(move_player::move_player)));
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    games::registry().configure(cfg);
+    cfg.service(web::resource("/leaderboard").route(web::get().to(leaderboard::leaderboard)));
+    cfg.service(web::resource("/tournament").route(web::post().to(tournament::create)));
+    cfg.service(web::resource("/tournament/{id}").route(web::get().to(tournament::show)));
+    cfg.service(web::resource("/tournament/{id}/join").route(web::post().to(tournament::join)));
+    cfg.service(web::resource("/tournament/{id}/close").route(web::post().to(tournament::close)));
 }
 
-/// Creates a new HTTP server on `addr` and runs it. This method blocks until the server is
-/// shutdown.
-pub fn run_server(addr: impl ToSocketAddrs) -> Result<(), io::Error> {
-    let sessions: Sessions = web::Data::new(Mutex::new(HashMap::new()));
+/// Creates a new HTTP server as set up by `config` and runs it. This method blocks until the server
+/// is shutdown. Sessions are saved to `storage` and restored from it when the server starts again.
+pub fn run_server(config: &Config, storage: Box<dyn Storage>) -> Result<(), io::Error> {
+    let registry = games::registry();
+    registry.check_defaults(&config.games)?;
+    let defaults: GameDefaults = web::Data::new(config.games.clone());
+    let secret = config.daily_secret.clone().unwrap_or_else(|| {
+        warn!(
+            "No daily_secret is configured, the daily challenge changes when the server restarts"
+        );
+        SessionToken::new().to_string()
+    });
+    let secret: DailySecret = web::Data::new(daily::Secret(secret));
+
+    let mut store = SessionStore::with_storage(config.session_limits(), storage);
+    let restored = store.restore(&registry)?;
+    info!("Restored {} saved sessions", restored);
 
+    let sessions: Sessions = web::Data::new(Mutex::new(store));
+    store::spawn_maintenance(sessions.clone());
+
+    let app_sessions = sessions.clone();
+    let json_limit = config.json_limit;
+    let rate_limit = RateLimit::new(config.rate_limits());
     HttpServer::new(move || {
         App::new()
+            .wrap(rate_limit.clone())
             .wrap(Logger::default())
-            .register_data(sessions.clone())
-            .data(web::JsonConfig::default().limit(4096))
+            .data(rate_limit.clone())
+            .register_data(app_sessions.clone())
+            .register_data(defaults.clone())
+            .register_data(secret.clone())
+            .data(web::JsonConfig::default().limit(json_limit))
+            .data(web::PayloadConfig::new(json_limit))
             .configure(routes)
     })
-    .bind(addr)?
+    .workers(config.workers)
+    .bind((config.host.as_str(), config.port))?
     .run()?;
+
+    sessions.lock().unwrap().flush()?;
     Ok(())
 }
 
@@ -70,22 +285,29 @@ impl FromRequest for SessionToken {
     type Config = ();
 
     fn from_request(req: &HttpRequest, _pl: &mut Payload) -> Self::Future {
-        let token = req
-            .headers()
-            .get("x-token")
-            .ok_or(ServiceError::MissingSessionToken)?;
-        let token = token.to_str().map_err(|_| ServiceError::InvalidTokenUTF8)?;
-        Ok(SessionToken(
-            uuid::Uuid::parse_str(token).map_err(|_| ServiceError::InvalidTokenUUID)?,
-        ))
+        SessionToken::read(req.headers(), req.query_string())
     }
 }
 
 impl SessionToken {
     /// Creates a new randomly generated token.
-    fn new() -> Self {
+    pub fn new() -> Self {
         SessionToken(uuid::Uuid::new_v4())
     }
+
+    /// Reads the token from the X-TOKEN header, or the `token` query parameter if there is none.
+    fn read(headers: &HeaderMap, query: &str) -> Result<Self, ServiceError> {
+        let token = match headers.get("x-token") {
+            Some(token) => token.to_str().map_err(|_| ServiceError::InvalidTokenUTF8)?,
+            None => query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("token="))
+                .ok_or(ServiceError::MissingSessionToken)?,
+        };
+        Ok(SessionToken(
+            uuid::Uuid::parse_str(token).map_err(|_| ServiceError::InvalidTokenUUID)?,
+        ))
+    }
 }
 
 #[cfg(test)]
@@ -110,6 +332,18 @@ mod tests {
             assert_eq!(token, Ok(SessionToken(uuid)));
         }
 
+        #[test]
+        /// Without the header the token should be extracted from the query string
+        fn extracted_from_query() {
+            let uuid = uuid::Uuid::new_v4();
+            let (req, mut payload) =
+                TestRequest::with_uri(&format!("/ws?token={}", uuid)).to_http_parts();
+
+            let token = block_on(SessionToken::from_request(&req, &mut payload));
+
+            assert_eq!(token, Ok(SessionToken(uuid)));
+        }
+
         #[test]
         /// If the token is missing it should return an error
         fn missing_token() {
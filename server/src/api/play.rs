@@ -0,0 +1,201 @@
+use super::{SessionToken, Sessions};
+use crate::error::ServiceError;
+use crate::maze::{GameStatus, Score};
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Serialize)]
+struct ReplayResponse<'a> {
+    /// The name of the game, see `NewGame::NAME`.
+    game: &'static str,
+    /// The player the game was played by.
+    player: &'a str,
+    #[serde(flatten)]
+    replay: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct StatusResponse {
+    status: GameStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<Score>,
+}
+
+/// The /act endpoint of every game. Passes the action in the body on to the game of the session
+/// and returns its result.
+pub fn act(
+    action: web::Json<Value>,
+    state: Sessions,
+    token: SessionToken,
+) -> Result<HttpResponse, ServiceError> {
+    let mut sessions = state.lock().unwrap();
+    let mut session = sessions.get_mut(&token)?;
+    Ok(HttpResponse::Ok().json(session.game_mut().act(action.into_inner())?))
+}
+
+/// The /observe endpoint of every game. Returns what the player can currently see.
+pub fn observe(state: Sessions, token: SessionToken) -> Result<HttpResponse, ServiceError> {
+    let sessions = state.lock().unwrap();
+    let session = sessions.get(&token)?;
+    Ok(HttpResponse::Ok().json(session.game().observe()))
+}
+
+/// The /status endpoint of every game. Returns whether the game is still being played and its
+/// score once it has been won.
+pub fn status(state: Sessions, token: SessionToken) -> Result<HttpResponse, ServiceError> {
+    let sessions = state.lock().unwrap();
+    let session = sessions.get(&token)?;
+    let game = session.game();
+    Ok(HttpResponse::Ok().json(StatusResponse {
+        status: game.status(),
+        score: game.score(),
+    }))
+}
+
+/// The /replay endpoint of every game. Returns the settings the game was started with and every
+/// turn taken in it so far, to play the game back with. The seed is left out while it is secret,
+/// see `SessionStore::hide_secret_seed`.
+pub fn replay(state: Sessions, token: SessionToken) -> Result<HttpResponse, ServiceError> {
+    let sessions = state.lock().unwrap();
+    let session = sessions.get(&token)?;
+    let mut replay = session.game().replay();
+    if let Some(config) = replay.get_mut("config") {
+        sessions.hide_secret_seed(session, config);
+    }
+    Ok(HttpResponse::Ok().json(ReplayResponse {
+        game: session.name,
+        player: &session.player,
+        replay,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{routes, Session, SessionStore},
+        SessionToken, Sessions,
+    };
+    use crate::game::{Game, NewGame};
+    use crate::games::maze::MazeGame;
+    use crate::maze::{GameMode, InfiniteMaze, Maze, Replay, ReplayPlayer};
+    use actix_web::{http::StatusCode, test, web, App};
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// A session store holding only `session`, and the token of the session.
+    fn store(session: Session) -> (Sessions, SessionToken) {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let token = SessionToken::new();
+        sessions.lock().unwrap().insert(token, session).unwrap();
+        (sessions, token)
+    }
+
+    #[test]
+    /// Actions are passed on to the game of the session
+    fn act_forwards_action_to_game() {
+        let (sessions, token) = store(Session::new(MazeGame::new(
+            Maze::new(9),
+            GameMode::Standard,
+        )));
+
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::post()
+            .uri("/game/maze/act")
+            .header("X-TOKEN", token.to_string())
+            .set_json(&serde_json::json!({ "moves": ["up"] }))
+            .to_request();
+
+        let response: serde_json::Value = test::read_response_json(&mut app, req);
+
+        assert_eq!(response["moved"], 0);
+        assert_eq!(response["blocked"], true);
+    }
+
+    #[test]
+    /// Actions a game does not understand are rejected
+    fn act_rejects_unknown_action() {
+        let (sessions, token) = store(Session::new(MazeGame::new(
+            Maze::new(9),
+            GameMode::Standard,
+        )));
+
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::post()
+            .uri("/game/maze/act")
+            .header("X-TOKEN", token.to_string())
+            .set_json(&serde_json::json!({ "jump": "up" }))
+            .to_request();
+
+        let response = test::call_service(&mut app, req);
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    /// A new game is still being played and has no score
+    fn status_of_new_game() {
+        let (sessions, token) = store(Session::new(MazeGame::new(
+            Maze::new(9),
+            GameMode::Standard,
+        )));
+
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::get()
+            .uri("/game/maze/status")
+            .header("X-TOKEN", token.to_string())
+            .to_request();
+
+        let response: serde_json::Value = test::read_response_json(&mut app, req);
+
+        assert_eq!(response, serde_json::json!({ "status": "playing" }));
+    }
+
+    #[test]
+    /// The replay of a session plays back to the same state the game is in
+    fn replay_reconstructs_game() {
+        let options = json!({ "width": 15, "height": 15, "seed": 11 });
+        let mut game = MazeGame::<Maze>::start(serde_json::from_value(options).unwrap()).unwrap();
+        for moves in &[json!(["up"]), json!(["down 3"]), json!(["right 2", "down"])] {
+            game.act(json!({ "moves": moves })).unwrap();
+        }
+        let player = game.board().player();
+        let (sessions, token) = store(Session::new(game).with_player("ada".to_string()));
+
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::get()
+            .uri("/game/maze/replay")
+            .header("X-TOKEN", token.to_string())
+            .to_request();
+
+        let replay: serde_json::Value = test::read_response_json(&mut app, req);
+
+        assert_eq!(replay["game"], "maze");
+        assert_eq!(replay["player"], "ada");
+        assert_eq!(replay["config"]["seed"], 11);
+        let mut replayed =
+            ReplayPlayer::new(serde_json::from_value::<Replay>(replay).unwrap()).unwrap();
+        replayed.seek(3).unwrap();
+        assert_eq!(replayed.turn(), 3);
+        assert_eq!(replayed.maze().player(), player);
+    }
+
+    #[test]
+    /// Sessions of one game cannot be played through the routes of another
+    fn session_of_other_game_not_found() {
+        let (sessions, token) = store(Session::new(MazeGame::new(
+            InfiniteMaze::new(1, 100),
+            GameMode::Speed,
+        )));
+
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::get()
+            .uri("/game/maze/move")
+            .header("X-TOKEN", token.to_string())
+            .to_request();
+
+        let response = test::call_service(&mut app, req);
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,340 @@
+use super::SessionToken;
+use crate::error::ServiceError;
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::Error;
+use futures::future::{ok, Either, FutureResult};
+use futures::Poll;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How many requests are checked between removing the buckets that have filled up again.
+const PRUNE_INTERVAL: u64 = 1024;
+
+/// How fast a client may make requests: `rate` requests per second on average, with bursts of up
+/// to `burst` requests at once.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Limit {
+    pub rate: f64,
+    pub burst: u32,
+}
+
+/// The limits of each session token and each client address. Either can be left out to not limit
+/// requests by it.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct RateLimits {
+    pub per_token: Option<Limit>,
+    pub per_address: Option<Limit>,
+}
+
+/// Middleware that limits requests with a token bucket for every session token and another for
+/// every client address. Requests are only let through if both of their buckets have room, and
+/// are otherwise answered with `ServiceError::TooManyRequests`.
+#[derive(Clone)]
+pub struct RateLimit {
+    limiter: Arc<Mutex<RateLimiter>>,
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limiter: Arc<Mutex<RateLimiter>>,
+}
+
+/// The buckets of every session token and client address that made requests recently.
+struct RateLimiter {
+    tokens: Option<Buckets<SessionToken>>,
+    addresses: Option<Buckets<IpAddr>>,
+    checks: u64,
+}
+
+struct Buckets<K> {
+    limit: Limit,
+    buckets: HashMap<K, Bucket>,
+}
+
+struct Bucket {
+    /// How many requests can be made right away as of `updated`.
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Limit {
+    /// How many requests `bucket` has room for at `now`.
+    fn refill(self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rate).min(f64::from(self.burst))
+    }
+}
+
+impl<K: Hash + Eq> Buckets<K> {
+    fn new(limit: Limit) -> Self {
+        Buckets {
+            limit,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// How long `key` has to wait until its next request is allowed, or `None` if it is allowed
+    /// now.
+    fn wait(&self, key: &K, now: Instant) -> Option<Duration> {
+        let tokens = self
+            .buckets
+            .get(key)
+            .map_or(f64::from(self.limit.burst), |bucket| {
+                self.limit.refill(bucket, now)
+            });
+        if tokens >= 1.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - tokens) / self.limit.rate))
+        }
+    }
+
+    /// Uses up a request of `key`.
+    fn take(&mut self, key: K, now: Instant) {
+        let limit = self.limit;
+        let bucket = self.buckets.entry(key).or_insert(Bucket {
+            tokens: f64::from(limit.burst),
+            updated: now,
+        });
+        bucket.tokens = limit.refill(bucket, now) - 1.0;
+        bucket.updated = now;
+    }
+
+    /// Forgets the buckets that are full again, as they are the same as new ones.
+    fn prune(&mut self, now: Instant) {
+        let limit = self.limit;
+        self.buckets
+            .retain(|_, bucket| limit.refill(bucket, now) < f64::from(limit.burst));
+    }
+}
+
+impl RateLimiter {
+    fn new(limits: RateLimits) -> Self {
+        RateLimiter {
+            tokens: limits.per_token.map(Buckets::new),
+            addresses: limits.per_address.map(Buckets::new),
+            checks: 0,
+        }
+    }
+
+    /// Counts a request of `token` from `address` if both have room for it, and otherwise says how
+    /// long to wait before trying again.
+    fn check(
+        &mut self,
+        token: Option<SessionToken>,
+        address: Option<IpAddr>,
+        now: Instant,
+    ) -> Result<(), ServiceError> {
+        self.checks += 1;
+        if self.checks % PRUNE_INTERVAL == 0 {
+            self.tokens
+                .iter_mut()
+                .for_each(|buckets| buckets.prune(now));
+            self.addresses
+                .iter_mut()
+                .for_each(|buckets| buckets.prune(now));
+        }
+
+        let wait = [
+            wait(&self.tokens, token.as_ref(), now),
+            wait(&self.addresses, address.as_ref(), now),
+        ]
+        .iter()
+        .flatten()
+        .max()
+        .copied();
+        if let Some(wait) = wait {
+            return Err(ServiceError::TooManyRequests {
+                retry_after: wait.as_secs() + if wait.subsec_nanos() > 0 { 1 } else { 0 },
+            });
+        }
+
+        if let (Some(buckets), Some(token)) = (&mut self.tokens, token) {
+            buckets.take(token, now);
+        }
+        if let (Some(buckets), Some(address)) = (&mut self.addresses, address) {
+            buckets.take(address, now);
+        }
+        Ok(())
+    }
+}
+
+/// How long `key` has to wait in `buckets`, if they limit requests and there is a key to limit.
+fn wait<K: Hash + Eq>(
+    buckets: &Option<Buckets<K>>,
+    key: Option<&K>,
+    now: Instant,
+) -> Option<Duration> {
+    match (buckets, key) {
+        (Some(buckets), Some(key)) => buckets.wait(key, now),
+        _ => None,
+    }
+}
+
+impl RateLimit {
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimit {
+            limiter: Arc::new(Mutex::new(RateLimiter::new(limits))),
+        }
+    }
+
+    /// Counts a request of `token` that does not pass through the middleware, such as a message
+    /// sent over an open websocket.
+    pub fn check(&self, token: SessionToken) -> Result<(), ServiceError> {
+        self.limiter
+            .lock()
+            .unwrap()
+            .check(Some(token), None, Instant::now())
+    }
+}
+
+impl<S, B> Transform<S> for RateLimit
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimitMiddleware<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitMiddleware {
+            service,
+            limiter: self.limiter.clone(),
+        })
+    }
+}
+
+impl<S, B> Service for RateLimitMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Either<S::Future, FutureResult<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let token = SessionToken::read(req.headers(), req.query_string()).ok();
+        let address = req.head().peer_addr.map(|address| address.ip());
+        let checked = self
+            .limiter
+            .lock()
+            .unwrap()
+            .check(token, address, Instant::now());
+
+        match checked {
+            Ok(()) => Either::A(self.service.call(req)),
+            Err(error) => Either::B(ok(req.error_response(error))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{routes, SessionStore, Sessions};
+    use super::*;
+    use actix_web::{http::header, http::StatusCode, test, web, App};
+
+    const LIMIT: Limit = Limit {
+        rate: 1.0,
+        burst: 2,
+    };
+
+    #[test]
+    /// A bucket lets a burst of requests through and then one request per refill
+    fn bursts_then_limited() {
+        let mut limiter = RateLimiter::new(RateLimits {
+            per_token: Some(LIMIT),
+            per_address: None,
+        });
+        let (token, start) = (Some(SessionToken::new()), Instant::now());
+        let later = |millis| start + Duration::from_millis(millis);
+
+        assert_eq!(limiter.check(token, None, start), Ok(()));
+        assert_eq!(limiter.check(token, None, start), Ok(()));
+        assert_eq!(
+            limiter.check(token, None, later(200)),
+            Err(ServiceError::TooManyRequests { retry_after: 1 })
+        );
+        assert_eq!(limiter.check(token, None, later(1000)), Ok(()));
+        assert_eq!(
+            limiter.check(token, None, later(1000)),
+            Err(ServiceError::TooManyRequests { retry_after: 1 })
+        );
+    }
+
+    #[test]
+    /// Each session token has a bucket of its own, while every token from an address shares the
+    /// bucket of the address
+    fn tokens_and_addresses_limited_separately() {
+        let mut limiter = RateLimiter::new(RateLimits {
+            per_token: Some(Limit {
+                rate: 1.0,
+                burst: 1,
+            }),
+            per_address: Some(LIMIT),
+        });
+        let now = Instant::now();
+        let (first, second, third) = (
+            Some(SessionToken::new()),
+            Some(SessionToken::new()),
+            Some(SessionToken::new()),
+        );
+        let (address, other) = ("10.0.0.1".parse().ok(), "10.0.0.2".parse().ok());
+
+        assert!(limiter.check(first, address, now).is_ok());
+        assert!(limiter.check(first, other, now).is_err());
+        assert!(limiter.check(second, address, now).is_ok());
+        assert!(limiter.check(third, address, now).is_err());
+        assert!(limiter.check(third, other, now).is_ok());
+    }
+
+    #[test]
+    /// Throttled requests are answered with 429 and say when to try again
+    fn throttled_requests_answered_with_429() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let limits = RateLimits {
+            per_token: Some(Limit {
+                rate: 0.5,
+                burst: 1,
+            }),
+            per_address: None,
+        };
+        let mut app = test::init_service(
+            App::new()
+                .wrap(RateLimit::new(limits))
+                .register_data(sessions)
+                .configure(routes),
+        );
+        let token = SessionToken::new().to_string();
+        let mut status = || {
+            let req = test::TestRequest::get()
+                .uri("/game/maze/status")
+                .header("X-TOKEN", token.as_str())
+                .to_request();
+            test::call_service(&mut app, req)
+        };
+
+        let allowed = status();
+        let throttled = status();
+
+        assert_eq!(allowed.status(), StatusCode::NOT_FOUND);
+        assert_eq!(throttled.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(throttled.headers().get(header::RETRY_AFTER).unwrap(), "2");
+    }
+}
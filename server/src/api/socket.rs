@@ -0,0 +1,233 @@
+use super::{RateLimit, SessionToken, Sessions};
+use crate::error::ServiceError;
+use crate::maze::{GameStatus, Score};
+use actix::{Actor, ActorContext, StreamHandler};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// The requests a client can send over /ws, one JSON message per text frame, such as
+/// `{"act": {"move": "up"}}`, `"observe"` or `"status"`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Request {
+    /// Performs an action, as with /act.
+    Act(Value),
+    /// Gets what the player can currently see, as with /observe.
+    Observe,
+    Status,
+}
+
+/// Sent back for every request, so the client always knows the status of the game.
+#[derive(Debug, Serialize)]
+struct Update {
+    /// The result of the action, for `act` requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    /// What the player can see, for `observe` requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    observe: Option<Value>,
+    status: GameStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<Score>,
+}
+
+/// A connection to /ws playing the session it was opened for.
+struct PlaySocket {
+    sessions: Sessions,
+    token: SessionToken,
+    /// Counts every message against the token, as they do not pass through the middleware.
+    rate_limit: Option<web::Data<RateLimit>>,
+}
+
+/// The /ws endpoint of every game. Opens a websocket to play the session of the token over, which
+/// saves a request per action. The token is taken from the X-TOKEN header or, as browsers cannot
+/// set headers on websockets, the `token` query parameter. Spectators can connect with their token
+/// to watch the game, but not act in it. Each message is rate limited like a request of the token.
+pub fn connect(
+    req: HttpRequest,
+    stream: web::Payload,
+    state: Sessions,
+    token: SessionToken,
+    rate_limit: Option<web::Data<RateLimit>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    state.lock().unwrap().get(&token)?;
+    ws::start(
+        PlaySocket {
+            sessions: state,
+            token,
+            rate_limit,
+        },
+        &req,
+        stream,
+    )
+}
+
+impl Actor for PlaySocket {
+    type Context = ws::WebsocketContext<Self>;
+}
+
+impl StreamHandler<ws::Message, ws::ProtocolError> for PlaySocket {
+    fn handle(&mut self, message: ws::Message, ctx: &mut Self::Context) {
+        match message {
+            ws::Message::Text(text) => match self
+                .limit()
+                .and_then(|()| reply(&self.sessions, &self.token, &text))
+            {
+                Ok(update) => ctx.text(json!(update).to_string()),
+                Err(error) => {
+                    ctx.text(json!({ "error": error.to_string() }).to_string());
+                    // There is nothing left to play once the session has expired.
+                    if error == ServiceError::SessionNotFound {
+                        ctx.stop();
+                    }
+                }
+            },
+            ws::Message::Binary(_) => ctx.text(
+                json!({ "error": "requests must be sent as JSON in text messages" }).to_string(),
+            ),
+            ws::Message::Ping(message) => ctx.pong(&message),
+            ws::Message::Close(_) => ctx.stop(),
+            ws::Message::Pong(_) | ws::Message::Nop => {}
+        }
+    }
+}
+
+impl PlaySocket {
+    fn limit(&self) -> Result<(), ServiceError> {
+        match &self.rate_limit {
+            Some(rate_limit) => rate_limit.check(self.token),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Answers a single request sent over the socket of `token`.
+fn reply(sessions: &Sessions, token: &SessionToken, text: &str) -> Result<Update, ServiceError> {
+    let request: Request = serde_json::from_str(text)
+        .map_err(|error| ServiceError::InvalidAction(error.to_string()))?;
+
+    let mut sessions = sessions.lock().unwrap();
+    let (result, observe) = match request {
+        Request::Act(action) => (
+            Some(sessions.get_mut(token)?.game_mut().act(action)?),
+            false,
+        ),
+        Request::Observe => (None, true),
+        Request::Status => (None, false),
+    };
+
+    let game = sessions.get(token)?.game();
+    Ok(Update {
+        result,
+        observe: if observe { Some(game.observe()) } else { None },
+        status: game.status(),
+        score: game.score(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Limit, RateLimits, Session, SessionStore};
+    use super::*;
+    use crate::games::maze::MazeGame;
+    use crate::maze::{GameMode, Maze};
+    use std::sync::Mutex;
+
+    fn session() -> (Sessions, SessionToken) {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let token = SessionToken::new();
+        sessions
+            .lock()
+            .unwrap()
+            .insert(
+                token,
+                Session::new(MazeGame::new(Maze::new(9), GameMode::Standard)),
+            )
+            .unwrap();
+        (sessions, token)
+    }
+
+    #[test]
+    /// Actions sent over the socket are played and answered with their result and the status
+    fn actions_answered_with_status() {
+        let (sessions, token) = session();
+
+        let update = reply(&sessions, &token, r#"{"act": {"moves": ["up"]}}"#).unwrap();
+
+        let update = json!(update);
+        assert_eq!(update["result"]["blocked"], true);
+        assert_eq!(update["status"], "playing");
+        assert!(update.get("observe").is_none());
+    }
+
+    #[test]
+    /// Observing returns the same map as /observe
+    fn observe_returns_map() {
+        let (sessions, token) = session();
+
+        let update = reply(&sessions, &token, r#""observe""#).unwrap();
+
+        let map = sessions
+            .lock()
+            .unwrap()
+            .get(&token)
+            .unwrap()
+            .game()
+            .observe();
+        assert_eq!(update.observe, Some(map));
+    }
+
+    #[test]
+    /// Requests that cannot be read and sessions that no longer exist are reported as errors
+    fn bad_requests_rejected() {
+        let (sessions, token) = session();
+
+        assert!(matches!(
+            reply(&sessions, &token, "up"),
+            Err(ServiceError::InvalidAction(_))
+        ));
+        assert!(matches!(
+            reply(&sessions, &SessionToken::new(), r#""status""#),
+            Err(ServiceError::SessionNotFound)
+        ));
+    }
+
+    #[test]
+    /// Spectators can observe the game over the socket but not act in it
+    fn spectators_cannot_act() {
+        let (sessions, token) = session();
+        let spectator = sessions.lock().unwrap().get(&token).unwrap().spectator();
+
+        assert!(reply(&sessions, &spectator, r#""observe""#).is_ok());
+        assert!(matches!(
+            reply(&sessions, &spectator, r#"{"act": "look"}"#),
+            Err(ServiceError::InsufficientPermission)
+        ));
+    }
+
+    #[test]
+    /// Every message sent over the socket uses up a request of the token
+    fn messages_rate_limited() {
+        let (sessions, token) = session();
+        let limits = RateLimits {
+            per_token: Some(Limit {
+                rate: 1.0,
+                burst: 1,
+            }),
+            per_address: None,
+        };
+        let socket = PlaySocket {
+            sessions,
+            token,
+            rate_limit: Some(web::Data::new(RateLimit::new(limits))),
+        };
+
+        assert_eq!(socket.limit(), Ok(()));
+        assert_eq!(
+            socket.limit(),
+            Err(ServiceError::TooManyRequests { retry_after: 1 })
+        );
+    }
+}
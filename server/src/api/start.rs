@@ -1,49 +1,318 @@
 use actix_web::HttpResponse;
 use log::info;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use super::{Session, SessionToken, Sessions};
+use super::accounts::{check_name, Accounts, ApiKey};
+use super::daily::DailyAttempt;
+use super::{GameDefaults, Session, SessionToken, Sessions, ANONYMOUS};
+use crate::error::ServiceError;
+use crate::game::NewGame;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Response {
     token: SessionToken,
+    /// A token that can watch the game but not play it, to hand out to spectators.
+    spectator_token: SessionToken,
+    config: Value,
+    /// When the session expires if no more requests are made for it, in seconds since the unix
+    /// epoch. Every request for the session pushes this back by the idle timeout.
+    expires_at: u64,
+    /// The attempt at the daily challenge the session plays, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    daily: Option<DailyAttempt>,
 }
 
-/// The /start endpoint. Creates a new game session and returns the token used to idenfiy this
-/// session.
-pub fn start(state: Sessions) -> HttpResponse {
+/// The /start endpoint of every game. Creates a new game session from the options in the body,
+/// taking any option left out from the configured defaults, and returns the token used to idenfiy
+/// this session together with the settings the game was started with. Besides the options of the
+/// game, the body can name the `player` the game is entered on the leaderboard under. Registered
+/// players send their API key instead, see `identify`.
+pub fn start<G: NewGame>(
+    body: String,
+    key: ApiKey,
+    defaults: Option<GameDefaults>,
+    state: Sessions,
+) -> Result<HttpResponse, ServiceError> {
+    let mut options = default_options::<G>(defaults);
+    merge(&mut options, read_options(&body)?)?;
+    let player = identify(&mut options, &key, state.lock().unwrap().accounts())?;
+    begin::<G>(options, player, None, state)
+}
+
+/// Starts a session of `G` with `options` for `player`, playing the daily challenge of `daily` if
+/// given, and responds with its tokens.
+pub fn begin<G: NewGame>(
+    options: Value,
+    player: String,
+    daily: Option<String>,
+    state: Sessions,
+) -> Result<HttpResponse, ServiceError> {
     let token = SessionToken::new();
-    let session = Session::new(9);
+    let game = G::start(serde_json::from_value(options).map_err(invalid_options)?)?;
+    let mut config = game.config();
+    let mut session = Session::new(game).with_player(player);
+    if let Some(date) = daily {
+        session = session.with_daily(date);
+    }
+    let spectator_token = session.spectator();
 
-    {
+    let (idle_timeout, daily) = {
         let mut sessions = state.lock().unwrap();
-        (*sessions).insert(token, session);
-    }
+        sessions.insert(token, session)?;
+        let session = sessions.get(&token)?;
+        sessions.hide_secret_seed(session, &mut config);
+        (sessions.limits().idle_timeout, session.daily.clone())
+    };
+
+    let expires_at = (SystemTime::now() + idle_timeout)
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0);
 
     info!("New game started with token: {}", token);
-    HttpResponse::Ok().json(Response { token })
+    Ok(HttpResponse::Ok().json(Response {
+        token,
+        spectator_token,
+        config,
+        expires_at,
+        daily,
+    }))
+}
+
+/// The options `G` is started with unless the request overrides them.
+pub fn default_options<G: NewGame>(defaults: Option<GameDefaults>) -> Value {
+    defaults
+        .and_then(|defaults| defaults.get(G::NAME).cloned())
+        .unwrap_or_else(|| Value::Object(Map::new()))
+}
+
+/// Reads the options sent in the body of a request, where an empty body has no options.
+pub fn read_options(body: &str) -> Result<Value, ServiceError> {
+    if body.trim().is_empty() {
+        Ok(Value::Object(Map::new()))
+    } else {
+        serde_json::from_str(body).map_err(invalid_options)
+    }
+}
+
+/// Adds every option of `requested` to `options`, replacing those already there.
+pub fn merge(options: &mut Value, requested: Value) -> Result<(), ServiceError> {
+    match (options, requested) {
+        (Value::Object(merged), Value::Object(requested)) => {
+            merged.extend(requested);
+            Ok(())
+        }
+        _ => Err(ServiceError::InvalidOptions(
+            "the options must be a JSON object".to_string(),
+        )),
+    }
+}
+
+/// Starts a session of `G` with `options`, leaving the player anonymous.
+pub fn session<G: NewGame>(options: Value) -> Result<Session, ServiceError> {
+    let options = serde_json::from_value(options).map_err(invalid_options)?;
+    Ok(Session::new(G::start(options)?))
+}
+
+/// Checks that `options` can be used to start the game `G`.
+pub fn check_options<G: NewGame>(options: Value) -> Result<(), ServiceError> {
+    serde_json::from_value::<G::Options>(options)
+        .map(|_| ())
+        .map_err(invalid_options)
+}
+
+/// Takes the name of the player out of the options, as it is not an option of the game itself.
+fn take_player(options: &mut Value) -> Result<String, ServiceError> {
+    match options
+        .as_object_mut()
+        .and_then(|options| options.remove("player"))
+    {
+        None => Ok(ANONYMOUS.to_string()),
+        Some(Value::String(player)) => check_name(&player)
+            .map(|()| player)
+            .map_err(ServiceError::InvalidOptions),
+        Some(_) => Err(ServiceError::InvalidOptions(
+            "the player must be a name".to_string(),
+        )),
+    }
+}
+
+/// Takes the player a game is started for out of the options. Games started with an API key are
+/// played by the player of the key. Otherwise the player is the one named in the options, which
+/// may not be the name of a registered player.
+pub fn identify(
+    options: &mut Value,
+    key: &ApiKey,
+    accounts: &Accounts,
+) -> Result<String, ServiceError> {
+    let named = take_player(options)?;
+    match &key.0 {
+        Some(key) => {
+            let player = accounts
+                .authenticate(key)
+                .ok_or(ServiceError::InvalidApiKey)?;
+            if named != ANONYMOUS && named != player {
+                return Err(ServiceError::InvalidOptions(
+                    "the player is named by the api key".to_string(),
+                ));
+            }
+            Ok(player.to_string())
+        }
+        None if accounts.is_registered(&named) => Err(ServiceError::InvalidApiKey),
+        None => Ok(named),
+    }
+}
+
+fn invalid_options(error: serde_json::Error) -> ServiceError {
+    ServiceError::InvalidOptions(error.to_string())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{super::routes, Response, Sessions};
-    use actix_web::{test, web, App};
-    use std::collections::HashMap;
+    use super::{
+        super::{
+            routes, Account, GameDefaults, MemoryStorage, SessionLimits, SessionStore, Storage,
+        },
+        Response, Sessions,
+    };
+    use crate::games;
+    use actix_web::{http::StatusCode, test, web, App};
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
     use std::sync::Mutex;
 
     #[test]
     /// Starting a new session adds the session to the store and returns the corresponding token
     fn new_session_stored_and_token_returned() {
-        let sessions: Sessions = web::Data::new(Mutex::new(HashMap::new()));
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
         let mut app =
             test::init_service(App::new().register_data(sessions.clone()).configure(routes));
-        let req = test::TestRequest::post().uri("/start").to_request();
+        let req = test::TestRequest::post()
+            .uri("/game/maze/start")
+            .to_request();
 
         let response: Response = test::read_response_json(&mut app, req);
 
+        let sessions = sessions.lock().unwrap();
+        assert!(sessions.get(&response.token).is_ok());
+        assert!(sessions.get(&response.spectator_token).is_ok());
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(response.config["width"], 9);
+        assert!(response.expires_at > 0);
+    }
+
+    #[test]
+    /// Options left out of the request are taken from the configured defaults of the game
+    fn defaults_fill_in_options() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let mut defaults = BTreeMap::new();
+        defaults.insert("maze".to_string(), json!({ "width": 15, "height": 15 }));
+        let defaults: GameDefaults = web::Data::new(defaults);
+        let mut app = test::init_service(
+            App::new()
+                .register_data(sessions)
+                .register_data(defaults)
+                .configure(routes),
+        );
+        let req = test::TestRequest::post()
+            .uri("/game/maze/start")
+            .set_json(&json!({ "height": 5 }))
+            .to_request();
+
+        let response: Response = test::read_response_json(&mut app, req);
+
+        assert_eq!(response.config["width"], 15);
+        assert_eq!(response.config["height"], 5);
+    }
+
+    #[test]
+    /// The player named in the request is kept with the session rather than passed to the game
+    fn player_kept_with_session() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let req = test::TestRequest::post()
+            .uri("/game/maze/start")
+            .set_json(&json!({ "player": "ada", "width": 11 }))
+            .to_request();
+
+        let response: Response = test::read_response_json(&mut app, req);
+
+        let sessions = sessions.lock().unwrap();
+        assert_eq!(sessions.get(&response.token).unwrap().player, "ada");
+        assert_eq!(response.config["width"], 11);
+        assert!(response.config.get("player").is_none());
+    }
+
+    #[test]
+    /// Registered players play under their name by sending their key, and nobody else can use it
+    fn registered_players_need_their_key() {
+        let (account, key) = Account::new("ada".to_string());
+        let mut storage = MemoryStorage::default();
+        storage.save_account(&account).unwrap();
+        let mut store = SessionStore::with_storage(SessionLimits::default(), Box::new(storage));
+        store.restore(&games::registry()).unwrap();
+        let sessions: Sessions = web::Data::new(Mutex::new(store));
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let mut start = |key: Option<&str>, body: Value| -> (StatusCode, Vec<u8>) {
+            let mut req = test::TestRequest::post().uri("/game/maze/start");
+            if let Some(key) = key {
+                req = req.header("X-API-KEY", key);
+            }
+            let response = test::call_service(&mut app, req.set_json(&body).to_request());
+            let status = response.status();
+            (status, test::read_body(response).to_vec())
+        };
+
+        let impostor = start(Some("guess"), json!({}));
+        let unkeyed = start(None, json!({ "player": "ada" }));
+        let (status, keyed) = start(Some(&key), json!({}));
+
+        assert_eq!(
+            impostor,
+            (StatusCode::UNAUTHORIZED, b"invalid api key".to_vec())
+        );
         assert_eq!(
-            Some(&response.token),
-            sessions.lock().unwrap().keys().next()
+            unkeyed,
+            (StatusCode::UNAUTHORIZED, b"invalid api key".to_vec())
         );
+        assert_eq!(status, StatusCode::OK);
+        let keyed: Value = serde_json::from_slice(&keyed).unwrap();
+        let token = serde_json::from_value(keyed["token"].clone()).unwrap();
+        assert_eq!(sessions.lock().unwrap().get(&token).unwrap().player, "ada");
+    }
+
+    #[test]
+    /// Options that are not valid JSON are rejected without starting a session
+    fn malformed_options_rejected() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let req = test::TestRequest::post()
+            .uri("/game/maze/start")
+            .set_payload("{\"width\": ")
+            .to_request();
+
+        let response = test::call_service(&mut app, req);
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(sessions.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    /// Games that are not registered do not exist
+    fn unknown_game_not_found() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::post()
+            .uri("/game/chess/start")
+            .to_request();
+
+        let response = test::call_service(&mut app, req);
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 }
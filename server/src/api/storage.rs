@@ -0,0 +1,355 @@
+use super::accounts::Account;
+use super::daily::DailyAttempt;
+use super::leaderboard::LeaderboardEntry;
+use super::tournament::{Tournament, TournamentId, TournamentRound};
+use super::{SessionToken, ANONYMOUS};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// A saved session, enough to rebuild its game after the server restarts.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SessionRecord {
+    /// The name of the game, see `NewGame::NAME`.
+    pub game: String,
+    /// The spectator token of the session. Sessions saved before spectators existed get a new one.
+    #[serde(default = "SessionToken::new")]
+    pub spectator: SessionToken,
+    #[serde(default = "anonymous")]
+    pub player: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily: Option<DailyAttempt>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tournament: Option<TournamentRound>,
+    /// How long the session had been idle when it was saved.
+    pub idle_ms: u64,
+    /// The game as returned by `Game::snapshot`.
+    pub snapshot: Value,
+}
+
+/// Where the `SessionStore` saves sessions and the leaderboard. Running games always live in
+/// memory, the storage only keeps copies of them to rebuild the games from when the server starts.
+pub trait Storage: Send {
+    fn save(&mut self, token: SessionToken, record: &SessionRecord) -> io::Result<()>;
+
+    fn remove(&mut self, token: SessionToken) -> io::Result<()>;
+
+    /// Every saved session.
+    fn load(&self) -> io::Result<Vec<(SessionToken, SessionRecord)>>;
+
+    /// Adds a won game to the saved leaderboard.
+    fn save_result(&mut self, entry: &LeaderboardEntry) -> io::Result<()>;
+
+    /// Removes a game that dropped off the leaderboard.
+    fn remove_result(&mut self, entry: &LeaderboardEntry) -> io::Result<()>;
+
+    /// Every game on the saved leaderboard, in the order they were finished.
+    fn load_results(&self) -> io::Result<Vec<LeaderboardEntry>>;
+
+    /// Saves a tournament, replacing the one saved with the same id.
+    fn save_tournament(&mut self, tournament: &Tournament) -> io::Result<()>;
+
+    fn load_tournaments(&self) -> io::Result<Vec<Tournament>>;
+
+    /// Saves the account of a registered player, replacing the one saved with the same name.
+    fn save_account(&mut self, account: &Account) -> io::Result<()>;
+
+    fn remove_account(&mut self, name: &str) -> io::Result<()>;
+
+    fn load_accounts(&self) -> io::Result<Vec<Account>>;
+
+    /// Makes sure everything saved so far has been written out.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps saved sessions in memory, so they are lost along with the running games when the server
+/// stops.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    records: HashMap<SessionToken, SessionRecord>,
+    results: Vec<LeaderboardEntry>,
+    tournaments: HashMap<TournamentId, Tournament>,
+    accounts: HashMap<String, Account>,
+}
+
+/// Saves sessions in a sled database on disk, so they survive restarts. The leaderboard, the
+/// tournaments and the accounts of players are kept in trees of their own.
+pub struct SledStorage {
+    db: sled::Db,
+    results: sled::Tree,
+    tournaments: sled::Tree,
+    accounts: sled::Tree,
+}
+
+impl Storage for MemoryStorage {
+    fn save(&mut self, token: SessionToken, record: &SessionRecord) -> io::Result<()> {
+        self.records.insert(token, record.clone());
+        Ok(())
+    }
+
+    fn remove(&mut self, token: SessionToken) -> io::Result<()> {
+        self.records.remove(&token);
+        Ok(())
+    }
+
+    fn load(&self) -> io::Result<Vec<(SessionToken, SessionRecord)>> {
+        Ok(self
+            .records
+            .iter()
+            .map(|(&token, record)| (token, record.clone()))
+            .collect())
+    }
+
+    fn save_result(&mut self, entry: &LeaderboardEntry) -> io::Result<()> {
+        self.results.push(entry.clone());
+        Ok(())
+    }
+
+    fn remove_result(&mut self, entry: &LeaderboardEntry) -> io::Result<()> {
+        if let Some(saved) = self.results.iter().position(|result| result == entry) {
+            self.results.remove(saved);
+        }
+        Ok(())
+    }
+
+    fn load_results(&self) -> io::Result<Vec<LeaderboardEntry>> {
+        Ok(self.results.clone())
+    }
+
+    fn save_tournament(&mut self, tournament: &Tournament) -> io::Result<()> {
+        self.tournaments.insert(tournament.id, tournament.clone());
+        Ok(())
+    }
+
+    fn load_tournaments(&self) -> io::Result<Vec<Tournament>> {
+        Ok(self.tournaments.values().cloned().collect())
+    }
+
+    fn save_account(&mut self, account: &Account) -> io::Result<()> {
+        self.accounts.insert(account.name.clone(), account.clone());
+        Ok(())
+    }
+
+    fn remove_account(&mut self, name: &str) -> io::Result<()> {
+        self.accounts.remove(name);
+        Ok(())
+    }
+
+    fn load_accounts(&self) -> io::Result<Vec<Account>> {
+        Ok(self.accounts.values().cloned().collect())
+    }
+}
+
+impl SledStorage {
+    /// Opens the database at `path`, creating it if it does not exist yet.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let db = sled::Db::open(path).map_err(sled_error)?;
+        Ok(SledStorage {
+            results: db.open_tree("leaderboard").map_err(sled_error)?,
+            tournaments: db.open_tree("tournaments").map_err(sled_error)?,
+            accounts: db.open_tree("accounts").map_err(sled_error)?,
+            db,
+        })
+    }
+}
+
+impl Storage for SledStorage {
+    fn save(&mut self, token: SessionToken, record: &SessionRecord) -> io::Result<()> {
+        self.db
+            .insert(token.0.as_bytes(), serde_json::to_vec(record)?)
+            .map_err(sled_error)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, token: SessionToken) -> io::Result<()> {
+        self.db.remove(token.0.as_bytes()).map_err(sled_error)?;
+        Ok(())
+    }
+
+    fn load(&self) -> io::Result<Vec<(SessionToken, SessionRecord)>> {
+        self.db
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry.map_err(sled_error)?;
+                let token = uuid::Uuid::from_slice(&key)
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+                Ok((SessionToken(token), serde_json::from_slice(&value)?))
+            })
+            .collect()
+    }
+
+    fn save_result(&mut self, entry: &LeaderboardEntry) -> io::Result<()> {
+        let value = serde_json::to_vec(entry)?;
+        self.results
+            .insert(result_key(entry.finished_at, &value), value)
+            .map_err(sled_error)?;
+        Ok(())
+    }
+
+    fn remove_result(&mut self, entry: &LeaderboardEntry) -> io::Result<()> {
+        let value = serde_json::to_vec(entry)?;
+        self.results
+            .remove(result_key(entry.finished_at, &value))
+            .map_err(sled_error)?;
+        Ok(())
+    }
+
+    fn load_results(&self) -> io::Result<Vec<LeaderboardEntry>> {
+        self.results
+            .iter()
+            .values()
+            .map(|value| Ok(serde_json::from_slice(&value.map_err(sled_error)?)?))
+            .collect()
+    }
+
+    fn save_tournament(&mut self, tournament: &Tournament) -> io::Result<()> {
+        self.tournaments
+            .insert(tournament.id.to_string(), serde_json::to_vec(tournament)?)
+            .map_err(sled_error)?;
+        Ok(())
+    }
+
+    fn load_tournaments(&self) -> io::Result<Vec<Tournament>> {
+        self.tournaments
+            .iter()
+            .values()
+            .map(|value| Ok(serde_json::from_slice(&value.map_err(sled_error)?)?))
+            .collect()
+    }
+
+    fn save_account(&mut self, account: &Account) -> io::Result<()> {
+        self.accounts
+            .insert(account.name.as_bytes(), serde_json::to_vec(account)?)
+            .map_err(sled_error)?;
+        Ok(())
+    }
+
+    fn remove_account(&mut self, name: &str) -> io::Result<()> {
+        self.accounts.remove(name.as_bytes()).map_err(sled_error)?;
+        Ok(())
+    }
+
+    fn load_accounts(&self) -> io::Result<Vec<Account>> {
+        self.accounts
+            .iter()
+            .values()
+            .map(|value| Ok(serde_json::from_slice(&value.map_err(sled_error)?)?))
+            .collect()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.db.flush().map_err(sled_error)?;
+        self.results.flush().map_err(sled_error)?;
+        self.tournaments.flush().map_err(sled_error)?;
+        self.accounts.flush().map_err(sled_error)?;
+        Ok(())
+    }
+}
+
+fn anonymous() -> String {
+    ANONYMOUS.to_string()
+}
+
+/// The key of a leaderboard entry, so it can be found again to be removed. Starts with the time the
+/// game was finished, so iterating the tree gives the entries in the order they were finished,
+/// followed by part of the hash of the entry to tell apart games finished in the same second.
+fn result_key(finished_at: u64, value: &[u8]) -> Vec<u8> {
+    let mut key = finished_at.to_be_bytes().to_vec();
+    key.extend_from_slice(&Sha256::digest(value)[..8]);
+    key
+}
+
+fn sled_error(error: sled::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Opens the database at `path` again once it has been closed. Sled lets go of its lock in the
+    /// background after the last handle is dropped, so the first attempts can find it still held.
+    fn reopen(path: &Path) -> SledStorage {
+        for _ in 0..100 {
+            if let Ok(storage) = SledStorage::open(path) {
+                return storage;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        SledStorage::open(path).unwrap()
+    }
+
+    fn result(points: u64) -> LeaderboardEntry {
+        LeaderboardEntry {
+            player: "ada".to_string(),
+            game: "maze".to_string(),
+            config: json!({ "width": 9 }),
+            moves: 12,
+            optimal_moves: 12,
+            points,
+            duration_ms: 100,
+            finished_at: points,
+            daily: None,
+            tournament: None,
+        }
+    }
+
+    #[test]
+    /// Saved sessions, leaderboard entries and tournaments are read back from the database after it
+    /// has been closed
+    fn sled_storage_survives_reopening() {
+        let path = std::env::temp_dir().join(format!("sessions-{}", SessionToken::new()));
+        let (kept, removed) = (SessionToken::new(), SessionToken::new());
+        let record = SessionRecord {
+            game: "maze".to_string(),
+            spectator: SessionToken::new(),
+            player: "ada".to_string(),
+            daily: None,
+            tournament: None,
+            idle_ms: 5,
+            snapshot: json!({ "history": [] }),
+        };
+        let mut tournament = Tournament {
+            id: TournamentId::new(),
+            name: "bots".to_string(),
+            game: "maze".to_string(),
+            rounds: vec![json!({ "seed": 1 })],
+            participants: Vec::new(),
+            organiser: "grace".to_string(),
+            closed: false,
+        };
+
+        {
+            let mut storage = SledStorage::open(&path).unwrap();
+            storage.save(kept, &record).unwrap();
+            storage.save(removed, &record).unwrap();
+            storage.remove(removed).unwrap();
+            storage.save_result(&result(1)).unwrap();
+            storage.save_result(&result(2)).unwrap();
+            storage.save_result(&result(3)).unwrap();
+            storage.remove_result(&result(3)).unwrap();
+            storage.save_tournament(&tournament).unwrap();
+            tournament.participants.push("ada".to_string());
+            storage.save_tournament(&tournament).unwrap();
+            storage.flush().unwrap();
+        }
+
+        let storage = reopen(&path);
+        let (loaded, results) = (storage.load().unwrap(), storage.load_results().unwrap());
+        let tournaments = storage.load_tournaments().unwrap();
+        drop(storage);
+        std::fs::remove_dir_all(&path).unwrap();
+
+        assert_eq!(loaded, vec![(kept, record)]);
+        assert_eq!(results, vec![result(1), result(2)]);
+        assert_eq!(tournaments, vec![tournament]);
+    }
+}
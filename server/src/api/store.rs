@@ -0,0 +1,656 @@
+use super::accounts::Accounts;
+use super::daily::Day;
+use super::events::EventBus;
+use super::leaderboard::{Leaderboard, LeaderboardEntry};
+use super::storage::{MemoryStorage, SessionRecord, Storage};
+use super::tournament::{Tournament, TournamentId};
+use super::{hide_seed, Registry, Session, SessionToken, Sessions, ANONYMOUS};
+use crate::error::ServiceError;
+use crate::game::Event;
+use actix_web::web::Bytes;
+use futures::sync::mpsc::UnboundedReceiver;
+use log::{info, warn};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often sessions that changed are saved and idle sessions are removed.
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Bounds on the sessions the server keeps, so that abandoned games do not pile up forever.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SessionLimits {
+    /// How long a session may go without any requests before it is removed.
+    pub idle_timeout: Duration,
+    /// The most sessions that may be running at once. Starting more games fails until some
+    /// sessions have expired.
+    pub max_sessions: usize,
+    /// The most tournaments that may be kept. Tournaments are never removed, so creating more
+    /// fails once there are this many.
+    pub max_tournaments: usize,
+}
+
+/// The sessions of every game being played. Sessions that have been idle for longer than the idle
+/// timeout are treated as gone and are removed by `reap`. Sessions that changed are saved to the
+/// storage by `flush`. Sessions can be watched with their spectator token, but only changed with
+/// the token of the player. The events of every change are published on the event bus, and games
+/// that are won are added to the leaderboard, except for unscored attempts at the daily challenge.
+/// The tournaments being played are kept here too, and saved along with the sessions.
+pub struct SessionStore {
+    sessions: HashMap<SessionToken, Session>,
+    /// The token of the player of each spectator token.
+    spectators: HashMap<SessionToken, SessionToken>,
+    limits: SessionLimits,
+    storage: Box<dyn Storage>,
+    changed: HashSet<SessionToken>,
+    events: EventBus,
+    leaderboard: Leaderboard,
+    /// The date and player of every scored attempt at the daily challenge of the current day.
+    daily_players: HashSet<(String, String)>,
+    tournaments: HashMap<TournamentId, Tournament>,
+    changed_tournaments: HashSet<TournamentId>,
+    /// The registered players. They are managed with the `player` command while the server is
+    /// stopped, so the store only reads them.
+    accounts: Accounts,
+}
+
+/// A session borrowed from the store to be changed. The events of its game are published when it
+/// is dropped, once the change is done.
+pub struct SessionMut<'a> {
+    token: SessionToken,
+    session: &'a mut Session,
+    events: &'a mut EventBus,
+    leaderboard: &'a mut Leaderboard,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        SessionLimits {
+            idle_timeout: Duration::from_secs(30 * 60),
+            max_sessions: 10_000,
+            max_tournaments: 1000,
+        }
+    }
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        SessionStore::new(SessionLimits::default())
+    }
+}
+
+impl SessionStore {
+    pub fn new(limits: SessionLimits) -> Self {
+        SessionStore::with_storage(limits, Box::new(MemoryStorage::default()))
+    }
+
+    pub fn with_storage(limits: SessionLimits, storage: Box<dyn Storage>) -> Self {
+        SessionStore {
+            sessions: HashMap::new(),
+            spectators: HashMap::new(),
+            limits,
+            storage,
+            changed: HashSet::new(),
+            events: EventBus::default(),
+            leaderboard: Leaderboard::default(),
+            daily_players: HashSet::new(),
+            tournaments: HashMap::new(),
+            changed_tournaments: HashSet::new(),
+            accounts: Accounts::default(),
+        }
+    }
+
+    /// Rebuilds the sessions, leaderboard, tournaments and accounts saved in the storage and
+    /// returns how many sessions were restored. Sessions whose game cannot be rebuilt are dropped.
+    pub fn restore(&mut self, registry: &Registry) -> io::Result<usize> {
+        let results = self.storage.load_results()?;
+        for entry in &results {
+            if let Some(date) = &entry.daily {
+                self.daily_players
+                    .insert((date.clone(), entry.player.clone()));
+            }
+        }
+        self.leaderboard = Leaderboard::new(results);
+        self.accounts = Accounts::new(self.storage.load_accounts()?);
+        for tournament in self.storage.load_tournaments()? {
+            self.tournaments.insert(tournament.id, tournament);
+        }
+        for (token, record) in self.storage.load()? {
+            match registry.restore(&record.game, record.snapshot) {
+                Ok(mut session) => {
+                    let idle = Duration::from_millis(record.idle_ms);
+                    if let Some(last_active) = Instant::now().checked_sub(idle) {
+                        session.last_active.set(last_active);
+                    }
+                    session.spectator = record.spectator;
+                    session.player = record.player;
+                    session.daily = record.daily;
+                    session.tournament = record.tournament;
+                    if let Some(daily) = session.daily.as_ref().filter(|daily| daily.scored) {
+                        self.daily_players
+                            .insert((daily.date.clone(), session.player.clone()));
+                    }
+                    self.spectators.insert(session.spectator, token);
+                    self.sessions.insert(token, session);
+                }
+                Err(error) => {
+                    warn!("Dropping saved session {}: {}", token, error);
+                    self.storage.remove(token)?;
+                }
+            }
+        }
+        self.reap();
+        Ok(self.sessions.len())
+    }
+
+    pub fn limits(&self) -> SessionLimits {
+        self.limits
+    }
+
+    /// The session of `token`, which may also be its spectator token. Requests of the player count
+    /// as activity on the session, but watching it does not keep it alive.
+    pub fn get(&self, token: &SessionToken) -> Result<&Session, ServiceError> {
+        let idle_timeout = self.limits.idle_timeout;
+        let player = self.spectators.get(token);
+        let session = self
+            .sessions
+            .get(player.unwrap_or(token))
+            .filter(|s| s.idle_for() <= idle_timeout)
+            .ok_or(ServiceError::SessionNotFound)?;
+        if player.is_none() {
+            session.touch();
+        }
+        Ok(session)
+    }
+
+    /// The session of `token` to change, counting as activity on it. Spectators may not change the
+    /// session.
+    pub fn get_mut(&mut self, token: &SessionToken) -> Result<SessionMut<'_>, ServiceError> {
+        if self.spectators.contains_key(token) {
+            return Err(ServiceError::InsufficientPermission);
+        }
+        let idle_timeout = self.limits.idle_timeout;
+        let session = self
+            .sessions
+            .get_mut(token)
+            .filter(|s| s.idle_for() <= idle_timeout)
+            .ok_or(ServiceError::SessionNotFound)?;
+        session.touch();
+        self.changed.insert(*token);
+        Ok(SessionMut {
+            token: *token,
+            session,
+            events: &mut self.events,
+            leaderboard: &mut self.leaderboard,
+        })
+    }
+
+    /// Removes the seed from `config`, the settings of `session` or of its replay, while the seed
+    /// would let players work out the game ahead of time. The seed of the daily challenge is only
+    /// shown once the day is over, and those of a tournament once it is closed.
+    pub fn hide_secret_seed(&self, session: &Session, config: &mut Value) {
+        let today = session
+            .daily
+            .as_ref()
+            .map_or(false, |daily| daily.date == Day::today().date());
+        let open = session.tournament.map_or(false, |round| {
+            self.tournaments
+                .get(&round.tournament)
+                .map_or(true, |tournament| !tournament.closed)
+        });
+        if today || open {
+            hide_seed(config);
+        }
+    }
+
+    pub fn leaderboard(&self) -> &Leaderboard {
+        &self.leaderboard
+    }
+
+    #[cfg(test)]
+    pub fn leaderboard_mut(&mut self) -> &mut Leaderboard {
+        &mut self.leaderboard
+    }
+
+    pub fn accounts(&self) -> &Accounts {
+        &self.accounts
+    }
+
+    pub fn tournament(&self, id: &TournamentId) -> Result<&Tournament, ServiceError> {
+        self.tournaments
+            .get(id)
+            .ok_or(ServiceError::TournamentNotFound)
+    }
+
+    /// Adds a new tournament, unless there are already as many as the limits allow.
+    pub fn add_tournament(&mut self, tournament: Tournament) -> Result<(), ServiceError> {
+        if self.tournaments.len() >= self.limits.max_tournaments {
+            return Err(ServiceError::TooManyTournaments {
+                max: self.limits.max_tournaments,
+            });
+        }
+        self.changed_tournaments.insert(tournament.id);
+        self.tournaments.insert(tournament.id, tournament);
+        Ok(())
+    }
+
+    /// Closes the tournament `id` for `organiser`, who must be the player that created it. The
+    /// sessions of its rounds are removed, as their seeds are no longer secret once it is closed
+    /// and the rounds could be worked out from them.
+    pub fn close_tournament(
+        &mut self,
+        id: &TournamentId,
+        organiser: &str,
+    ) -> Result<&Tournament, ServiceError> {
+        if self.tournament(id)?.organiser != organiser {
+            return Err(ServiceError::NotOrganiser);
+        }
+        let unfinished: Vec<SessionToken> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| {
+                session
+                    .tournament
+                    .map_or(false, |round| round.tournament == *id)
+            })
+            .map(|(&token, _)| token)
+            .collect();
+        self.remove_sessions(&unfinished);
+        self.changed_tournaments.insert(*id);
+        let tournament = self
+            .tournaments
+            .get_mut(id)
+            .ok_or(ServiceError::TournamentNotFound)?;
+        tournament.closed = true;
+        Ok(tournament)
+    }
+
+    /// Enters `player` into the tournament `id` with the sessions of its rounds. Nothing is added
+    /// if the tournament is closed, the player already joined or there is no room for every
+    /// session.
+    pub fn join_tournament(
+        &mut self,
+        id: &TournamentId,
+        player: &str,
+        rounds: Vec<(SessionToken, Session)>,
+    ) -> Result<(), ServiceError> {
+        let tournament = self.tournament(id)?;
+        if tournament.closed {
+            return Err(ServiceError::TournamentClosed);
+        }
+        if tournament.participants.iter().any(|p| p == player) {
+            return Err(ServiceError::AlreadyJoined);
+        }
+        if self.sessions.len() + rounds.len() > self.limits.max_sessions {
+            self.reap();
+        }
+        if self.sessions.len() + rounds.len() > self.limits.max_sessions {
+            return Err(ServiceError::TooManySessions {
+                max: self.limits.max_sessions,
+            });
+        }
+
+        if let Some(tournament) = self.tournaments.get_mut(id) {
+            tournament.participants.push(player.to_string());
+            self.changed_tournaments.insert(*id);
+        }
+        for (token, session) in rounds {
+            self.insert(token, session)?;
+        }
+        Ok(())
+    }
+
+    /// Follows the events of the session of `token`, which may also be its spectator token.
+    pub fn subscribe(
+        &mut self,
+        token: &SessionToken,
+    ) -> Result<UnboundedReceiver<Bytes>, ServiceError> {
+        let session = self.get(token)?;
+        let mut config = session.game().config();
+        self.hide_secret_seed(session, &mut config);
+        let started = Event::Started { config };
+        let player = *self.spectators.get(token).unwrap_or(token);
+        Ok(self.events.subscribe(player, &started))
+    }
+
+    /// Adds a new session, first removing expired sessions if there is no room for it. Attempts at
+    /// the daily challenge are scored if they are the first of a named player that day.
+    pub fn insert(
+        &mut self,
+        token: SessionToken,
+        mut session: Session,
+    ) -> Result<(), ServiceError> {
+        if self.sessions.len() >= self.limits.max_sessions {
+            self.reap();
+        }
+        if self.sessions.len() >= self.limits.max_sessions {
+            return Err(ServiceError::TooManySessions {
+                max: self.limits.max_sessions,
+            });
+        }
+
+        if let Some(daily) = &mut session.daily {
+            daily.scored = session.player != ANONYMOUS
+                && self
+                    .daily_players
+                    .insert((daily.date.clone(), session.player.clone()));
+        }
+        self.spectators.insert(session.spectator, token);
+        self.sessions.insert(token, session);
+        self.changed.insert(token);
+        Ok(())
+    }
+
+    /// Removes every session that has been idle for longer than the idle timeout and returns how
+    /// many were removed. Attempts at the daily challenges of past days are forgotten too.
+    pub fn reap(&mut self) -> usize {
+        let today = Day::today().date();
+        self.daily_players.retain(|(date, _)| *date >= today);
+
+        let idle_timeout = self.limits.idle_timeout;
+        let expired: Vec<SessionToken> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| session.idle_for() > idle_timeout)
+            .map(|(&token, _)| token)
+            .collect();
+
+        self.remove_sessions(&expired);
+        expired.len()
+    }
+
+    /// Removes the sessions of `tokens` along with their saved copies, and ends their events.
+    fn remove_sessions(&mut self, tokens: &[SessionToken]) {
+        for token in tokens {
+            if let Some(session) = self.sessions.remove(token) {
+                self.spectators.remove(&session.spectator);
+            }
+            self.events.publish(*token, &Event::Expired);
+            self.events.close(token);
+            self.changed.remove(token);
+            if let Err(error) = self.storage.remove(*token) {
+                warn!("Could not remove saved session {}: {}", token, error);
+            }
+        }
+    }
+
+    /// Saves every session and tournament that changed and every game won since the last flush, and
+    /// returns how many sessions were saved.
+    pub fn flush(&mut self) -> io::Result<usize> {
+        let changed: Vec<SessionToken> = self.changed.drain().collect();
+        for &token in &changed {
+            if let Some(session) = self.sessions.get(&token) {
+                let record = SessionRecord {
+                    game: session.name.to_string(),
+                    spectator: session.spectator,
+                    player: session.player.clone(),
+                    daily: session.daily.clone(),
+                    tournament: session.tournament,
+                    idle_ms: session.idle_for().as_millis() as u64,
+                    snapshot: session.game.snapshot(),
+                };
+                self.storage.save(token, &record)?;
+            }
+        }
+        for entry in self.leaderboard.take_unsaved() {
+            self.storage.save_result(&entry)?;
+        }
+        for entry in self.leaderboard.take_dropped() {
+            self.storage.remove_result(&entry)?;
+        }
+        for id in self.changed_tournaments.drain() {
+            if let Some(tournament) = self.tournaments.get(&id) {
+                self.storage.save_tournament(tournament)?;
+            }
+        }
+        self.storage.flush()?;
+        Ok(changed.len())
+    }
+
+    #[cfg(test)]
+    fn into_storage(self) -> Box<dyn Storage> {
+        self.storage
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+impl Deref for SessionMut<'_> {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        self.session
+    }
+}
+
+impl DerefMut for SessionMut<'_> {
+    fn deref_mut(&mut self) -> &mut Session {
+        self.session
+    }
+}
+
+impl Drop for SessionMut<'_> {
+    fn drop(&mut self) {
+        for event in self.session.game.take_events() {
+            if let Event::Won { score } = &event {
+                // Only the first attempt of a player at the daily challenge counts.
+                if self
+                    .session
+                    .daily
+                    .as_ref()
+                    .map_or(true, |daily| daily.scored)
+                {
+                    self.leaderboard
+                        .add(LeaderboardEntry::new(self.session, score));
+                }
+            }
+            self.events.publish(self.token, &event);
+        }
+    }
+}
+
+/// Starts a thread that removes idle sessions from `sessions` and saves the ones that changed for
+/// as long as the server runs.
+pub fn spawn_maintenance(sessions: Sessions) -> thread::JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(MAINTENANCE_INTERVAL);
+        let mut sessions = sessions.lock().unwrap();
+
+        let removed = sessions.reap();
+        if removed > 0 {
+            info!("Removed {} idle sessions", removed);
+        }
+        if let Err(error) = sessions.flush() {
+            warn!("Could not save sessions: {}", error);
+        }
+    })
+}
+
+impl Session {
+    /// Marks the session as active now.
+    fn touch(&self) {
+        self.last_active.set(Instant::now());
+    }
+
+    /// How long it has been since the session was last used.
+    pub fn idle_for(&self) -> Duration {
+        self.last_active.get().elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::NewGame;
+    use crate::games::{self, maze::MazeGame};
+    use crate::maze::{Direction, GameMode, InfiniteMaze, Maze};
+
+    fn session_idle_for(idle: Duration) -> Session {
+        let session = Session::new(MazeGame::new(Maze::new(9), GameMode::Standard));
+        session.last_active.set(Instant::now() - idle);
+        session
+    }
+
+    #[test]
+    /// Sessions that have been idle for too long are not found and are removed by the reaper
+    fn idle_sessions_expire() {
+        let mut store = SessionStore::new(SessionLimits {
+            idle_timeout: Duration::from_secs(60),
+            max_sessions: 10,
+            ..SessionLimits::default()
+        });
+        let (active, idle) = (SessionToken::new(), SessionToken::new());
+        store
+            .insert(active, session_idle_for(Duration::from_secs(30)))
+            .unwrap();
+        store
+            .insert(idle, session_idle_for(Duration::from_secs(90)))
+            .unwrap();
+
+        assert!(store.get(&active).is_ok());
+        assert_eq!(store.get(&idle).err(), Some(ServiceError::SessionNotFound));
+        assert_eq!(
+            store.get_mut(&idle).err(),
+            Some(ServiceError::SessionNotFound)
+        );
+        assert_eq!(store.reap(), 1);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    /// Using a session resets how long it has been idle
+    fn activity_keeps_session_alive() {
+        let mut store = SessionStore::default();
+        let token = SessionToken::new();
+        store
+            .insert(token, session_idle_for(Duration::from_secs(20 * 60)))
+            .unwrap();
+
+        store.get_mut(&token).unwrap();
+
+        assert!(store.get(&token).unwrap().idle_for() < Duration::from_secs(60));
+    }
+
+    #[test]
+    /// New sessions are refused once the store is full of sessions that are still active, but
+    /// replace expired ones
+    fn full_store_refuses_new_sessions() {
+        let mut store = SessionStore::new(SessionLimits {
+            idle_timeout: Duration::from_secs(60),
+            max_sessions: 2,
+            ..SessionLimits::default()
+        });
+        store
+            .insert(
+                SessionToken::new(),
+                session_idle_for(Duration::from_secs(0)),
+            )
+            .unwrap();
+        store
+            .insert(
+                SessionToken::new(),
+                session_idle_for(Duration::from_secs(90)),
+            )
+            .unwrap();
+
+        assert_eq!(
+            store.insert(
+                SessionToken::new(),
+                session_idle_for(Duration::from_secs(0))
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            store.insert(
+                SessionToken::new(),
+                session_idle_for(Duration::from_secs(0))
+            ),
+            Err(ServiceError::TooManySessions { max: 2 })
+        );
+    }
+
+    #[test]
+    /// Spectator tokens can watch a session but not change it or keep it alive
+    fn spectators_only_watch() {
+        let mut store = SessionStore::default();
+        let token = SessionToken::new();
+        let session = session_idle_for(Duration::from_secs(20 * 60));
+        let spectator = session.spectator();
+        store.insert(token, session).unwrap();
+
+        assert!(store.get(&spectator).is_ok());
+        assert_eq!(
+            store.get_mut(&spectator).err(),
+            Some(ServiceError::InsufficientPermission)
+        );
+        assert!(store.get(&token).unwrap().idle_for() < Duration::from_secs(60));
+
+        store
+            .get(&spectator)
+            .unwrap()
+            .last_active
+            .set(Instant::now() - Duration::from_secs(40 * 60));
+        store.reap();
+        assert_eq!(
+            store.get(&spectator).err(),
+            Some(ServiceError::SessionNotFound)
+        );
+    }
+
+    #[test]
+    /// Saved sessions are rebuilt exactly as they were, including the moves taken in them
+    fn sessions_restored_from_storage() {
+        let mut store = SessionStore::default();
+        let registry = games::registry();
+        let (maze, infinite) = (SessionToken::new(), SessionToken::new());
+
+        let options = serde_json::json!({ "width": 11, "height": 7, "visibility": "revealed" });
+        let game = MazeGame::<Maze>::start(serde_json::from_value(options).unwrap()).unwrap();
+        store.insert(maze, Session::new(game)).unwrap();
+        let game = MazeGame::<InfiniteMaze>::start(Default::default()).unwrap();
+        store.insert(infinite, Session::new(game)).unwrap();
+
+        for &token in &[maze, infinite] {
+            let mut session = store.get_mut(&token).unwrap();
+            let game = session.game_mut();
+            for &direction in &[Direction::Down, Direction::Right, Direction::Right] {
+                let _ = game.act(serde_json::json!({ "move": direction }));
+            }
+            game.act(serde_json::json!("look")).unwrap();
+        }
+        assert_eq!(store.flush().unwrap(), 2);
+        let saved: Vec<_> = [maze, infinite]
+            .iter()
+            .map(|token| {
+                let session = store.get(token).unwrap();
+                let game = session.game();
+                (
+                    session.spectator(),
+                    game.observe(),
+                    game.snapshot()["history"].clone(),
+                )
+            })
+            .collect();
+
+        let mut restored =
+            SessionStore::with_storage(SessionLimits::default(), store.into_storage());
+        assert_eq!(restored.restore(&registry).unwrap(), 2);
+
+        for (spectator, map, history) in saved {
+            let game = restored.get(&spectator).unwrap().game();
+            assert_eq!(game.observe(), map);
+            assert_eq!(game.snapshot()["history"], history);
+        }
+    }
+}
@@ -0,0 +1,528 @@
+use super::accounts::{Accounts, ApiKey};
+use super::leaderboard::Leaderboard;
+use super::start::{identify, merge, read_options};
+use super::{hide_seed, GameDefaults, SessionToken, Sessions, ANONYMOUS};
+use crate::error::ServiceError;
+use crate::games;
+use actix_web::{web, HttpResponse};
+use derive_more::Display;
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Reverse;
+
+/// The most rounds a tournament can have, as every participant gets a session for each of them.
+const MAX_ROUNDS: usize = 100;
+
+/// Identifies a tournament in the /tournament routes.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Default, Display, Hash, Clone, Copy)]
+pub struct TournamentId(uuid::Uuid);
+
+/// A set of games that every participant plays with the same settings, such as the same seeds, so
+/// that their results can be compared.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Tournament {
+    pub id: TournamentId,
+    pub name: String,
+    /// The game every round is played in, see `NewGame::NAME`.
+    pub game: String,
+    /// The settings of each round, with every option filled in so that each participant gets the
+    /// same game.
+    pub rounds: Vec<Value>,
+    /// The players that joined, in the order they joined.
+    pub participants: Vec<String>,
+    /// The registered player that created the tournament, who is the only one that can close it.
+    #[serde(default)]
+    pub organiser: String,
+    /// Whether the organiser closed the tournament. Nobody can join a closed tournament, and the
+    /// seeds of its rounds are only shown once it is closed.
+    #[serde(default)]
+    pub closed: bool,
+}
+
+/// The round of a tournament a session plays.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TournamentRound {
+    pub tournament: TournamentId,
+    /// The index of the round in `Tournament::rounds`.
+    pub round: usize,
+}
+
+/// The body of POST /tournament, such as
+/// `{"name": "bots", "game": "maze", "rounds": [{"seed": 1}, {"seed": 2, "width": 21}]}`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewTournament {
+    name: String,
+    game: String,
+    /// The options of each round, as sent to /start. Options left out are taken from the
+    /// configured defaults, and a random seed is picked for rounds without one.
+    rounds: Vec<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TournamentView {
+    #[serde(flatten)]
+    tournament: Tournament,
+    standings: Vec<Standing>,
+}
+
+/// How a participant is doing, summed over the rounds they have won.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Standing {
+    /// The place of the participant counted from 1, shared by participants that are tied.
+    rank: usize,
+    player: String,
+    /// How many rounds the participant reached the exit of.
+    won: usize,
+    points: u64,
+    moves: usize,
+    duration_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Joined {
+    player: String,
+    /// A session for each round, in the order of the rounds.
+    sessions: Vec<RoundSession>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RoundSession {
+    round: usize,
+    token: SessionToken,
+    spectator_token: SessionToken,
+    /// The settings of the round, without its seed.
+    config: Value,
+}
+
+impl TournamentId {
+    /// Creates a new randomly generated id.
+    pub fn new() -> Self {
+        TournamentId(uuid::Uuid::new_v4())
+    }
+}
+
+impl Standing {
+    /// Orders standings best first: the most rounds won, then the most points, then the least
+    /// time taken and then the fewest moves.
+    fn key(&self) -> (Reverse<usize>, Reverse<u64>, u64, usize) {
+        (
+            Reverse(self.won),
+            Reverse(self.points),
+            self.duration_ms,
+            self.moves,
+        )
+    }
+}
+
+/// The standings of every participant of `tournament` from its results on the leaderboard.
+fn standings(tournament: &Tournament, leaderboard: &Leaderboard) -> Vec<Standing> {
+    let mut standings: Vec<Standing> = tournament
+        .participants
+        .iter()
+        .map(|player| Standing {
+            rank: 0,
+            player: player.clone(),
+            won: 0,
+            points: 0,
+            moves: 0,
+            duration_ms: 0,
+        })
+        .collect();
+
+    for entry in leaderboard.tournament_results(tournament.id) {
+        if let Some(standing) = standings.iter_mut().find(|s| s.player == entry.player) {
+            standing.won += 1;
+            standing.points += entry.points;
+            standing.moves += entry.moves;
+            standing.duration_ms += entry.duration_ms;
+        }
+    }
+
+    standings.sort_by(|a, b| a.key().cmp(&b.key()).then_with(|| a.player.cmp(&b.player)));
+    let mut previous = None;
+    for (place, standing) in standings.iter_mut().enumerate() {
+        let key = standing.key();
+        standing.rank = match previous {
+            Some((previous_key, rank)) if previous_key == key => rank,
+            _ => place + 1,
+        };
+        previous = Some((key, standing.rank));
+    }
+    standings
+}
+
+/// The tournament as shown to everyone, with the seeds of its rounds left out until it is closed.
+fn view(tournament: &Tournament, leaderboard: &Leaderboard) -> TournamentView {
+    let mut shown = tournament.clone();
+    if !shown.closed {
+        shown.rounds.iter_mut().for_each(hide_seed);
+    }
+    TournamentView {
+        tournament: shown,
+        standings: standings(tournament, leaderboard),
+    }
+}
+
+/// The registered player of `key`, who organises tournaments.
+fn organiser(key: &ApiKey, accounts: &Accounts) -> Result<String, ServiceError> {
+    match &key.0 {
+        Some(key) => accounts
+            .authenticate(key)
+            .map(str::to_string)
+            .ok_or(ServiceError::InvalidApiKey),
+        None => Err(ServiceError::MissingApiKey),
+    }
+}
+
+/// The POST /tournament endpoint. Creates a tournament of the rounds in the body and returns it
+/// with its id. The settings of every round are fixed when the tournament is created, so each
+/// participant plays the same games, and their seeds are kept secret until the tournament is
+/// closed. Only registered players can organise tournaments, so the request needs an API key.
+pub fn create(
+    body: web::Json<NewTournament>,
+    key: ApiKey,
+    defaults: Option<GameDefaults>,
+    state: Sessions,
+) -> Result<HttpResponse, ServiceError> {
+    let organiser = organiser(&key, state.lock().unwrap().accounts())?;
+    let body = body.into_inner();
+    if body.rounds.is_empty() || body.rounds.len() > MAX_ROUNDS {
+        return Err(ServiceError::InvalidOptions(format!(
+            "a tournament has 1 to {} rounds",
+            MAX_ROUNDS
+        )));
+    }
+
+    let registry = games::registry();
+    let defaults = defaults.and_then(|defaults| defaults.get(&body.game).cloned());
+    let mut rounds = Vec::new();
+    for requested in body.rounds {
+        let mut options = defaults
+            .clone()
+            .unwrap_or_else(|| Value::Object(Map::new()));
+        merge(&mut options, requested)?;
+        rounds.push(registry.start(&body.game, options)?.game().config());
+    }
+
+    let tournament = Tournament {
+        id: TournamentId::new(),
+        name: body.name,
+        game: body.game,
+        rounds,
+        participants: Vec::new(),
+        organiser,
+        closed: false,
+    };
+
+    let mut sessions = state.lock().unwrap();
+    let created = view(&tournament, sessions.leaderboard());
+    sessions.add_tournament(tournament)?;
+    info!(
+        "New tournament created by {} with id: {}",
+        created.tournament.organiser, created.tournament.id
+    );
+    Ok(HttpResponse::Ok().json(created))
+}
+
+/// The GET /tournament/{id} endpoint. Returns the tournament with the standings of its
+/// participants, best first. The seeds of the rounds are only included once it is closed.
+pub fn show(id: web::Path<TournamentId>, state: Sessions) -> Result<HttpResponse, ServiceError> {
+    let sessions = state.lock().unwrap();
+    let tournament = sessions.tournament(&id)?;
+    Ok(HttpResponse::Ok().json(view(tournament, sessions.leaderboard())))
+}
+
+/// The POST /tournament/{id}/join endpoint. Enters the `player` named in the body, or the player of
+/// the API key, into the tournament and returns a session for each of its rounds. Each player can
+/// only join once, so every round is played once by every participant. The settings of the rounds
+/// are returned without their seeds.
+pub fn join(
+    id: web::Path<TournamentId>,
+    body: String,
+    key: ApiKey,
+    state: Sessions,
+) -> Result<HttpResponse, ServiceError> {
+    let mut requested = read_options(&body)?;
+    let player = identify(&mut requested, &key, state.lock().unwrap().accounts())?;
+    if player == ANONYMOUS {
+        return Err(ServiceError::InvalidOptions(
+            "participants of a tournament need a `player` name".to_string(),
+        ));
+    }
+    if let Some(option) = requested
+        .as_object()
+        .and_then(|options| options.keys().next())
+    {
+        return Err(ServiceError::InvalidOptions(format!(
+            "the rounds of a tournament are the same for everyone, so `{}` cannot be chosen",
+            option
+        )));
+    }
+
+    let tournament = state.lock().unwrap().tournament(&id)?.clone();
+    let registry = games::registry();
+    let mut started = Vec::new();
+    let mut joined = Joined {
+        player: player.clone(),
+        sessions: Vec::new(),
+    };
+    for (round, config) in tournament.rounds.iter().enumerate() {
+        let token = SessionToken::new();
+        let session = registry
+            .start(&tournament.game, config.clone())?
+            .with_player(player.clone())
+            .with_tournament(TournamentRound {
+                tournament: tournament.id,
+                round,
+            });
+        let mut config = config.clone();
+        hide_seed(&mut config);
+        joined.sessions.push(RoundSession {
+            round,
+            token,
+            spectator_token: session.spectator(),
+            config,
+        });
+        started.push((token, session));
+    }
+
+    state
+        .lock()
+        .unwrap()
+        .join_tournament(&tournament.id, &player, started)?;
+    info!("{} joined tournament {}", player, tournament.id);
+    Ok(HttpResponse::Ok().json(joined))
+}
+
+/// The POST /tournament/{id}/close endpoint. Closes the tournament for its organiser, who sends
+/// their API key, and returns it with its final standings and the seeds of its rounds. The rounds
+/// can no longer be joined or played.
+pub fn close(
+    id: web::Path<TournamentId>,
+    key: ApiKey,
+    state: Sessions,
+) -> Result<HttpResponse, ServiceError> {
+    let mut sessions = state.lock().unwrap();
+    let organiser = organiser(&key, sessions.accounts())?;
+    let closed = sessions.close_tournament(&id, &organiser)?.clone();
+    info!("{} closed tournament {}", organiser, closed.id);
+    Ok(HttpResponse::Ok().json(view(&closed, sessions.leaderboard())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::leaderboard::LeaderboardEntry;
+    use super::super::{routes, Account, MemoryStorage, SessionLimits, SessionStore, Storage};
+    use super::*;
+    use actix_web::{dev::ServiceResponse, http::StatusCode, test, App};
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Sessions of a server that allows `max_tournaments`, with an organiser registered under the
+    /// returned API key.
+    fn organised(max_tournaments: usize) -> (Sessions, String) {
+        let (account, key) = Account::new("organiser".to_string());
+        let mut storage = MemoryStorage::default();
+        storage.save_account(&account).unwrap();
+        let limits = SessionLimits {
+            max_tournaments,
+            ..SessionLimits::default()
+        };
+        let mut store = SessionStore::with_storage(limits, Box::new(storage));
+        store.restore(&games::registry()).unwrap();
+        (web::Data::new(Mutex::new(store)), key)
+    }
+
+    fn post_tournament(sessions: &Sessions, key: Option<&str>) -> ServiceResponse {
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let mut req = test::TestRequest::post().uri("/tournament");
+        if let Some(key) = key {
+            req = req.header("X-API-KEY", key);
+        }
+        let req = req
+            .set_json(&json!({
+                "name": "bots",
+                "game": "maze",
+                "rounds": [{ "seed": 1, "width": 5, "height": 5 }, { "width": 7 }]
+            }))
+            .to_request();
+        test::call_service(&mut app, req)
+    }
+
+    fn create_tournament(sessions: &Sessions, key: &str) -> TournamentView {
+        let body = test::read_body(post_tournament(sessions, Some(key)));
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test]
+    /// Every participant gets sessions for the same mazes, with a seed picked for rounds that had
+    /// none
+    fn participants_play_same_rounds() {
+        let (sessions, key) = organised(1);
+        let created = create_tournament(&sessions, &key);
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let mut join = |player: &str| -> Joined {
+            let req = test::TestRequest::post()
+                .uri(&format!("/tournament/{}/join", created.tournament.id))
+                .set_json(&json!({ "player": player }))
+                .to_request();
+            test::read_response_json(&mut app, req)
+        };
+
+        let (first, second) = (join("bot-a"), join("bot-b"));
+
+        let sessions = sessions.lock().unwrap();
+        let tournament = sessions.tournament(&created.tournament.id).unwrap();
+        assert_eq!(tournament.rounds[0]["seed"], 1);
+        assert!(tournament.rounds[1]["seed"].is_u64());
+        for joined in &[first, second] {
+            assert_eq!(joined.sessions.len(), 2);
+            for (session, config) in joined.sessions.iter().zip(&tournament.rounds) {
+                let game = sessions.get(&session.token).unwrap().game();
+                assert_eq!(&game.config(), config);
+                assert_eq!(session.config["width"], config["width"]);
+                assert!(session.config.get("seed").is_none());
+            }
+        }
+        assert_eq!(tournament.participants, vec!["bot-a", "bot-b"]);
+    }
+
+    #[test]
+    /// The seeds of the rounds are kept from players until the organiser closes the tournament,
+    /// which also ends its rounds
+    fn seeds_shown_once_closed() {
+        let (sessions, key) = organised(1);
+        let created = create_tournament(&sessions, &key);
+        let id = created.tournament.id;
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let mut post = |path: &str, key: Option<&str>| {
+            let mut req = test::TestRequest::post().uri(&format!("/tournament/{}/{}", id, path));
+            if let Some(key) = key {
+                req = req.header("X-API-KEY", key);
+            }
+            test::call_service(
+                &mut app,
+                req.set_json(&json!({ "player": "bot-a" })).to_request(),
+            )
+        };
+
+        assert_eq!(post("join", None).status(), StatusCode::OK);
+        let unkeyed = post("close", None).status();
+        let closed: TournamentView =
+            serde_json::from_slice(&test::read_body(post("close", Some(&key)))).unwrap();
+        let late = post("join", None).status();
+
+        assert!(created.tournament.rounds[0].get("seed").is_none());
+        assert_eq!(created.tournament.rounds[0]["width"], 5);
+        assert_eq!(unkeyed, StatusCode::UNAUTHORIZED);
+        assert!(closed.tournament.closed);
+        assert_eq!(closed.tournament.rounds[0]["seed"], 1);
+        assert_eq!(late, StatusCode::CONFLICT);
+        let mut sessions = sessions.lock().unwrap();
+        assert!(sessions.is_empty());
+        assert_eq!(
+            sessions.close_tournament(&id, "someone-else").err(),
+            Some(ServiceError::NotOrganiser)
+        );
+    }
+
+    #[test]
+    /// Players join once and need a name to be ranked under
+    fn joining_twice_rejected() {
+        let (sessions, key) = organised(1);
+        let created = create_tournament(&sessions, &key);
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let mut join = |body: Value| {
+            let req = test::TestRequest::post()
+                .uri(&format!("/tournament/{}/join", created.tournament.id))
+                .set_json(&body)
+                .to_request();
+            test::call_service(&mut app, req).status()
+        };
+
+        assert_eq!(join(json!({ "player": "bot-a" })), StatusCode::OK);
+        assert_eq!(join(json!({ "player": "bot-a" })), StatusCode::CONFLICT);
+        assert_eq!(join(json!({})), StatusCode::BAD_REQUEST);
+        assert_eq!(sessions.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    /// Only registered players can create tournaments, and only as many as the server allows
+    fn organisers_need_their_key() {
+        let (sessions, key) = organised(1);
+
+        let unkeyed = post_tournament(&sessions, None).status();
+        let impostor = post_tournament(&sessions, Some("guess")).status();
+        let created = post_tournament(&sessions, Some(&key)).status();
+        let over_limit = post_tournament(&sessions, Some(&key)).status();
+
+        assert_eq!(unkeyed, StatusCode::UNAUTHORIZED);
+        assert_eq!(impostor, StatusCode::UNAUTHORIZED);
+        assert_eq!(created, StatusCode::OK);
+        assert_eq!(over_limit, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    /// Participants are ranked on rounds won, then points, then time, and tied participants share
+    /// their rank
+    fn standings_ranked_with_tie_breakers() {
+        let tournament = Tournament {
+            id: TournamentId::new(),
+            name: "bots".to_string(),
+            game: "maze".to_string(),
+            rounds: vec![json!({ "seed": 1 }), json!({ "seed": 2 })],
+            participants: ["slow", "fast", "tied", "once", "never"]
+                .iter()
+                .map(|player| player.to_string())
+                .collect(),
+            organiser: "grace".to_string(),
+            closed: false,
+        };
+        let result = |player: &str, round: usize, points: u64, duration_ms: u64| LeaderboardEntry {
+            player: player.to_string(),
+            game: "maze".to_string(),
+            config: tournament.rounds[round].clone(),
+            moves: 10,
+            optimal_moves: 10,
+            points,
+            duration_ms,
+            finished_at: 0,
+            daily: None,
+            tournament: Some(TournamentRound {
+                tournament: tournament.id,
+                round,
+            }),
+        };
+        let leaderboard = Leaderboard::new(vec![
+            result("slow", 0, 500, 3000),
+            result("slow", 1, 500, 3000),
+            result("fast", 0, 500, 1000),
+            result("fast", 1, 500, 1000),
+            result("tied", 0, 500, 1000),
+            result("tied", 1, 500, 1000),
+            result("once", 0, 1000, 100),
+        ]);
+
+        let ranks: Vec<(usize, String, usize)> = standings(&tournament, &leaderboard)
+            .into_iter()
+            .map(|standing| (standing.rank, standing.player, standing.won))
+            .collect();
+
+        assert_eq!(
+            ranks,
+            vec![
+                (1, "fast".to_string(), 2),
+                (1, "tied".to_string(), 2),
+                (3, "slow".to_string(), 2),
+                (4, "once".to_string(), 1),
+                (5, "never".to_string(), 0),
+            ]
+        );
+    }
+}
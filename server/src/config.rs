@@ -0,0 +1,362 @@
+use crate::api::{Limit, RateLimits, SessionLimits};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use structopt::StructOpt;
+
+/// The settings of the server. Each setting is taken from its command line flag if given, then
+/// from its `SPROCKIT_*` environment variable, then from the config file and otherwise has a
+/// default.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    /// The number of threads handling requests.
+    pub workers: usize,
+    /// The largest request body accepted, in bytes.
+    pub json_limit: usize,
+    /// How many seconds a session may be left idle before it is removed.
+    pub session_ttl: u64,
+    pub max_sessions: usize,
+    pub max_tournaments: usize,
+    /// How many requests per second each session token may make, or 0 for no limit.
+    pub token_rate: f64,
+    /// How many requests a session token may make at once before it is held to `token_rate`.
+    pub token_burst: u32,
+    /// How many requests per second each client address may make, or 0 for no limit.
+    pub address_rate: f64,
+    /// How many requests a client address may make at once before it is held to `address_rate`.
+    pub address_burst: u32,
+    /// The sled database sessions are saved to, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_db: Option<PathBuf>,
+    /// The secret the seeds of the daily challenge are derived from. A random one is picked when
+    /// the server starts if none is given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daily_secret: Option<String>,
+    pub log_format: LogFormat,
+    /// The options each game is started with unless the request to /start overrides them, by the
+    /// name of the game. Only read from the config file.
+    pub games: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// The command line flags of the server.
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Hosts the sprokit games")]
+pub struct Args {
+    /// A TOML file to read settings from
+    #[structopt(long, env = "SPROCKIT_CONFIG", parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// The address to listen on, such as 0.0.0.0 to accept connections from anywhere
+    #[structopt(long, env = "SPROCKIT_HOST")]
+    host: Option<String>,
+    #[structopt(long, env = "SPROCKIT_PORT")]
+    port: Option<u16>,
+    /// The number of threads handling requests [default: number of CPUs]
+    #[structopt(long, env = "SPROCKIT_WORKERS")]
+    workers: Option<usize>,
+    /// The largest request body accepted, in bytes
+    #[structopt(long, env = "SPROCKIT_JSON_LIMIT")]
+    json_limit: Option<usize>,
+    /// How many seconds a session may be left idle before it is removed
+    #[structopt(long, env = "SPROCKIT_SESSION_TTL")]
+    session_ttl: Option<u64>,
+    /// The most sessions that may be running at once
+    #[structopt(long, env = "SPROCKIT_MAX_SESSIONS")]
+    max_sessions: Option<usize>,
+    /// The most tournaments that may be kept
+    #[structopt(long, env = "SPROCKIT_MAX_TOURNAMENTS")]
+    max_tournaments: Option<usize>,
+    /// How many requests per second each session token may make, or 0 for no limit
+    #[structopt(long, env = "SPROCKIT_TOKEN_RATE")]
+    token_rate: Option<f64>,
+    /// How many requests a session token may make at once before it is held to its rate
+    #[structopt(long, env = "SPROCKIT_TOKEN_BURST")]
+    token_burst: Option<u32>,
+    /// How many requests per second each client address may make, or 0 for no limit
+    #[structopt(long, env = "SPROCKIT_ADDRESS_RATE")]
+    address_rate: Option<f64>,
+    /// How many requests a client address may make at once before it is held to its rate
+    #[structopt(long, env = "SPROCKIT_ADDRESS_BURST")]
+    address_burst: Option<u32>,
+    /// A sled database to save sessions to, so they survive restarts
+    #[structopt(long, env = "SPROCKIT_SESSION_DB", parse(from_os_str))]
+    session_db: Option<PathBuf>,
+    /// The secret the seeds of the daily challenge are derived from [default: random]
+    #[structopt(long, env = "SPROCKIT_DAILY_SECRET")]
+    daily_secret: Option<String>,
+    /// How log lines are written, either text or json
+    #[structopt(long, env = "SPROCKIT_LOG_FORMAT")]
+    log_format: Option<LogFormat>,
+    /// Prints the settings that would be used as TOML and exits
+    #[structopt(long)]
+    pub print_config: bool,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// The commands run instead of the server.
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Manages registered players and their API keys in the session database. Run it while the
+    /// server is stopped, which reads the players when it starts.
+    Player(PlayerCommand),
+}
+
+#[derive(Debug, StructOpt)]
+pub enum PlayerCommand {
+    /// Registers a player and prints their new API key
+    Add { name: String },
+    /// Replaces the API key of a player and prints the new one
+    RotateKey { name: String },
+    /// Removes a player, so their name is free to use again
+    Remove { name: String },
+    /// Lists every registered player
+    List,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let limits = SessionLimits::default();
+        Config {
+            host: "localhost".to_string(),
+            port: 4000,
+            workers: num_cpus::get(),
+            json_limit: 4096,
+            session_ttl: limits.idle_timeout.as_secs(),
+            max_sessions: limits.max_sessions,
+            max_tournaments: limits.max_tournaments,
+            token_rate: 10.0,
+            token_burst: 20,
+            address_rate: 50.0,
+            address_burst: 100,
+            session_db: None,
+            daily_secret: None,
+            log_format: LogFormat::Text,
+            games: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file named by `args`, if any, and applies the flags and environment
+    /// variables on top of it.
+    pub fn load(args: &Args) -> io::Result<Config> {
+        let mut config = match &args.config {
+            Some(path) => toml::from_str(&fs::read_to_string(path)?).map_err(|error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), error),
+                )
+            })?,
+            None => Config::default(),
+        };
+
+        if let Some(host) = &args.host {
+            config.host = host.clone();
+        }
+        if let Some(port) = args.port {
+            config.port = port;
+        }
+        if let Some(workers) = args.workers {
+            config.workers = workers;
+        }
+        if let Some(json_limit) = args.json_limit {
+            config.json_limit = json_limit;
+        }
+        if let Some(session_ttl) = args.session_ttl {
+            config.session_ttl = session_ttl;
+        }
+        if let Some(max_sessions) = args.max_sessions {
+            config.max_sessions = max_sessions;
+        }
+        if let Some(max_tournaments) = args.max_tournaments {
+            config.max_tournaments = max_tournaments;
+        }
+        if let Some(token_rate) = args.token_rate {
+            config.token_rate = token_rate;
+        }
+        if let Some(token_burst) = args.token_burst {
+            config.token_burst = token_burst;
+        }
+        if let Some(address_rate) = args.address_rate {
+            config.address_rate = address_rate;
+        }
+        if let Some(address_burst) = args.address_burst {
+            config.address_burst = address_burst;
+        }
+        if let Some(session_db) = &args.session_db {
+            config.session_db = Some(session_db.clone());
+        }
+        if let Some(daily_secret) = &args.daily_secret {
+            config.daily_secret = Some(daily_secret.clone());
+        }
+        if let Some(log_format) = args.log_format {
+            config.log_format = log_format;
+        }
+        Ok(config)
+    }
+
+    pub fn session_limits(&self) -> SessionLimits {
+        SessionLimits {
+            idle_timeout: Duration::from_secs(self.session_ttl),
+            max_sessions: self.max_sessions,
+            max_tournaments: self.max_tournaments,
+        }
+    }
+
+    pub fn rate_limits(&self) -> RateLimits {
+        RateLimits {
+            per_token: limit(self.token_rate, self.token_burst),
+            per_address: limit(self.address_rate, self.address_burst),
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("the config serializes to toml")
+    }
+}
+
+/// The limit of `rate` requests per second with bursts of `burst`, unless the rate is 0. Bursts
+/// smaller than one request would never let a request through, so they are raised to one.
+fn limit(rate: f64, burst: u32) -> Option<Limit> {
+    if rate > 0.0 {
+        Some(Limit {
+            rate,
+            burst: burst.max(1),
+        })
+    } else {
+        None
+    }
+}
+
+impl LogFormat {
+    /// Sets up the logger to write lines in this format. Which lines are written is still chosen
+    /// with `RUST_LOG`.
+    pub fn init_logger(self) {
+        let mut builder = env_logger::Builder::from_default_env();
+        if self == LogFormat::Json {
+            builder.format(|buf, record| {
+                let line = serde_json::json!({
+                    "time": buf.timestamp().to_string(),
+                    "level": record.level().to_string(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                });
+                writeln!(buf, "{}", line)
+            });
+        }
+        builder.init();
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format `{}`, use text or json", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `contents` to a new config file and returns its path.
+    fn config_file(contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sprockit-{}.toml", uuid::Uuid::new_v4()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    /// Flags override the config file, which overrides the defaults
+    fn flags_override_config_file() {
+        let path = config_file(
+            r#"
+            host = "0.0.0.0"
+            port = 5000
+
+            [games.maze]
+            width = 15
+            "#,
+        );
+        let args = Args::from_iter(&[
+            "server",
+            "--config",
+            path.to_str().unwrap(),
+            "--port",
+            "6000",
+        ]);
+
+        let config = Config::load(&args).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.host, "0.0.0.0");
+        assert_eq!(config.port, 6000);
+        assert_eq!(config.json_limit, Config::default().json_limit);
+        assert_eq!(config.games["maze"], serde_json::json!({ "width": 15 }));
+    }
+
+    #[test]
+    /// Misspelt settings in the config file are reported rather than ignored
+    fn unknown_settings_rejected() {
+        let path = config_file("prot = 5000");
+        let args = Args::from_iter(&["server", "--config", path.to_str().unwrap()]);
+
+        let error = Config::load(&args).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    /// The printed config can be used as a config file to get the same settings again
+    fn printed_config_reads_back() {
+        let mut config = Config::default();
+        config.log_format = LogFormat::Json;
+        config.session_db = Some(PathBuf::from("sessions.db"));
+        config.daily_secret = Some("shh".to_string());
+        config
+            .games
+            .insert("maze".to_string(), serde_json::json!({ "width": 21 }));
+
+        assert_eq!(toml::from_str::<Config>(&config.to_toml()).unwrap(), config);
+    }
+
+    #[test]
+    /// A rate of 0 turns off the limit
+    fn zero_rate_not_limited() {
+        let args = Args::from_iter(&["server", "--token-rate", "0", "--address-rate", "2.5"]);
+
+        let limits = Config::load(&args).unwrap().rate_limits();
+
+        assert_eq!(limits.per_token, None);
+        assert_eq!(
+            limits.per_address,
+            Some(Limit {
+                rate: 2.5,
+                burst: 100
+            })
+        );
+    }
+}
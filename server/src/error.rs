@@ -1,4 +1,4 @@
-use actix_web::{error::ResponseError, HttpResponse};
+use actix_web::{error::ResponseError, http::header, HttpResponse};
 use derive_more::Display;
 use maze;
 use serde::Serialize;
@@ -15,6 +15,38 @@ pub enum ServiceError {
     SessionNotFound,
     #[display(fmt = "direction blocked")]
     DirectionBlocked,
+    #[display(fmt = "view radius too large")]
+    ViewRadiusTooLarge { max: usize },
+    #[display(fmt = "invalid move command: {}", _0)]
+    InvalidMoveCommand(String),
+    #[display(fmt = "too many moves")]
+    TooManyMoves { max: usize },
+    #[display(fmt = "invalid action: {}", _0)]
+    InvalidAction(String),
+    #[display(fmt = "invalid options: {}", _0)]
+    InvalidOptions(String),
+    #[display(fmt = "too many sessions")]
+    TooManySessions { max: usize },
+    #[display(fmt = "insufficient permission")]
+    InsufficientPermission,
+    #[display(fmt = "solution rejected: {}", _0)]
+    SolutionRejected(String),
+    #[display(fmt = "tournament not found")]
+    TournamentNotFound,
+    #[display(fmt = "already joined")]
+    AlreadyJoined,
+    #[display(fmt = "tournament closed")]
+    TournamentClosed,
+    #[display(fmt = "not the organiser")]
+    NotOrganiser,
+    #[display(fmt = "invalid api key")]
+    InvalidApiKey,
+    #[display(fmt = "missing api key")]
+    MissingApiKey,
+    #[display(fmt = "too many tournaments")]
+    TooManyTournaments { max: usize },
+    #[display(fmt = "too many requests")]
+    TooManyRequests { retry_after: u64 },
 }
 
 impl From<maze::DirectionBlocked> for ServiceError {
@@ -23,6 +55,12 @@ impl From<maze::DirectionBlocked> for ServiceError {
     }
 }
 
+impl From<maze::InvalidSize> for ServiceError {
+    fn from(error: maze::InvalidSize) -> Self {
+        ServiceError::InvalidOptions(error.to_string())
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct ErrorResponse<'a> {
     pub error: &'a str,
@@ -35,7 +73,7 @@ impl ResponseError for ServiceError {
             ServiceError::MissingSessionToken => {
                 HttpResponse::BadRequest().json(ErrorResponse{
                     error: &format!("{}", self),
-                    help: "The token can be set with the header `X-TOKEN`Session tokens are obtained by sending a post request to /start",
+                    help: "The token can be set with the header `X-TOKEN` or the `token` query parameter. Session tokens are obtained by sending a post request to /start",
                 })
             }
             ServiceError::InvalidTokenUTF8 => {
@@ -62,6 +100,104 @@ impl ResponseError for ServiceError {
                     help: "The direction was blocked, you need to pick another way.",
                 })
             }
+            ServiceError::ViewRadiusTooLarge { max } => {
+                HttpResponse::BadRequest().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: &format!("The radius of the view can be at most {}.", max),
+                })
+            }
+            ServiceError::InvalidMoveCommand(_) => {
+                HttpResponse::BadRequest().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: "Each move is a direction optionally followed by how often to repeat it, such as `up` or `right 5`.",
+                })
+            }
+            ServiceError::TooManyMoves { max } => {
+                HttpResponse::BadRequest().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: &format!("A single request can move the player at most {} times.", max),
+                })
+            }
+            ServiceError::InvalidAction(_) => {
+                HttpResponse::BadRequest().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: "The action is not one the game understands, such as `{\"move\": \"up\"}` for the maze.",
+                })
+            }
+            ServiceError::InvalidOptions(_) => {
+                HttpResponse::BadRequest().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: "The options are sent to /start as a JSON object in the body, such as `{\"width\": 15, \"height\": 9, \"generator\": \"backtracker\"}` for the maze. Every option may be left out.",
+                })
+            }
+            ServiceError::TooManySessions { max } => {
+                HttpResponse::ServiceUnavailable().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: &format!("The server is already running {} games. Try again once some have finished, sessions expire after they have been left idle for a while.", max),
+                })
+            }
+            ServiceError::InsufficientPermission => {
+                HttpResponse::Forbidden().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: "Spectator tokens can only watch a game. Playing it needs the `token` returned by /start rather than the `spectator_token`.",
+                })
+            }
+            ServiceError::SolutionRejected(_) => {
+                HttpResponse::BadRequest().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: "The moves must lead from the start of the maze generated from the config to its exit without running into a wall, such as `{\"config\": {\"width\": 9, \"height\": 9, \"seed\": 42}, \"moves\": [\"right 2\", \"down\"]}`.",
+                })
+            }
+            ServiceError::TournamentNotFound => {
+                HttpResponse::NotFound().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: "No tournament was found for the given id. Tournaments are created by sending a post request to /tournament",
+                })
+            }
+            ServiceError::AlreadyJoined => {
+                HttpResponse::Conflict().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: "Each player can only join a tournament once, and plays every round with the sessions returned when they joined.",
+                })
+            }
+            ServiceError::TournamentClosed => {
+                HttpResponse::Conflict().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: "The tournament was closed by its organiser, so no more players can join. Its standings and the seeds of its rounds can still be read from /tournament/{id}.",
+                })
+            }
+            ServiceError::NotOrganiser => {
+                HttpResponse::Forbidden().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: "Only the registered player that created a tournament can close it, by sending their API key in the `X-API-KEY` header.",
+                })
+            }
+            ServiceError::InvalidApiKey => {
+                HttpResponse::Unauthorized().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: "Registered players start their games with their API key in the `X-API-KEY` header, and their name cannot be used without it. Keys are handed out by the admin of the server.",
+                })
+            }
+            ServiceError::MissingApiKey => {
+                HttpResponse::Unauthorized().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: "Only registered players can create tournaments, by sending their API key in the `X-API-KEY` header. Keys are handed out by the admin of the server.",
+                })
+            }
+            ServiceError::TooManyTournaments { max } => {
+                HttpResponse::ServiceUnavailable().json(ErrorResponse{
+                    error: &format!("{}", self),
+                    help: &format!("The server already keeps {} tournaments, which is as many as it allows. The admin of the server can raise `max_tournaments`.", max),
+                })
+            }
+            ServiceError::TooManyRequests { retry_after } => {
+                HttpResponse::TooManyRequests()
+                    .header(header::RETRY_AFTER, retry_after.to_string())
+                    .json(ErrorResponse{
+                        error: &format!("{}", self),
+                        help: &format!("Requests are limited for each session token and each client address. Wait {} seconds, as given by the `Retry-After` header, before trying again.", retry_after),
+                    })
+            }
         }
     }
 }
@@ -0,0 +1,101 @@
+use crate::error::ServiceError;
+use crate::maze::{Direction, GameStatus, Score};
+use actix_web::web;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::any::Any;
+
+/// A game that can be played through the server. Each session owns one game and the server
+/// forwards the actions of the player to it, so new games only need to implement this trait and
+/// `NewGame` to get sessions, tokens and error handling for free.
+pub trait Game: Any + Send {
+    /// Performs an action sent by the player and returns the result of it. What actions look like
+    /// is up to each game.
+    fn act(&mut self, action: Value) -> Result<Value, ServiceError>;
+
+    /// What the player can currently see of the game.
+    fn observe(&self) -> Value;
+
+    fn status(&self) -> GameStatus;
+
+    /// The score of the game once it has been won.
+    fn score(&self) -> Option<Score> {
+        None
+    }
+
+    /// The settings the game was started with, with every option that was left out filled in.
+    fn config(&self) -> Value;
+
+    /// Everything needed to rebuild the game exactly as it is now with `NewGame::restore`, so that
+    /// sessions can be saved and outlive the server.
+    fn snapshot(&self) -> Value;
+
+    /// Every action taken in the game so far with when it was taken and its outcome, together with
+    /// the settings the game was started with, so that it can be played back.
+    fn replay(&self) -> Value;
+
+    /// Takes the events of everything that happened in the game since they were last taken. The
+    /// session store publishes them to the followers of the session after every change.
+    fn take_events(&mut self) -> Vec<Event> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// Something that happened in a session, streamed to its followers on /events.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Event {
+    /// Sent first to every new follower, with the settings the game was started with.
+    Started {
+        config: Value,
+    },
+    /// The player took `steps` steps and is now at `position`.
+    Moved {
+        position: Value,
+        steps: usize,
+    },
+    Blocked {
+        direction: Direction,
+    },
+    /// The player saw `tiles` tiles they had not seen before.
+    Revealed {
+        tiles: usize,
+    },
+    Won {
+        score: Score,
+    },
+    /// The session was removed after being left idle, which ends the stream.
+    Expired,
+}
+
+/// How a game is started and which routes it adds to the common ones. Games are mounted under
+/// `/game/{NAME}` by registering them with the `Registry`.
+pub trait NewGame: Game + Sized {
+    /// The name the game is mounted under, which also identifies the game of a saved session.
+    const NAME: &'static str;
+
+    /// The options the player can start a new game with, sent as a JSON object in the body of
+    /// /start. Options left out are taken from the defaults in the config, so every option should
+    /// have a default of its own too.
+    type Options: DeserializeOwned + 'static;
+
+    fn start(options: Self::Options) -> Result<Self, ServiceError>;
+
+    /// Rebuilds a game from a snapshot taken with `Game::snapshot`.
+    fn restore(snapshot: Value) -> Result<Self, ServiceError>;
+
+    /// The options of the daily challenge, which take precedence over the configured defaults so
+    /// that everyone gets the same game from the `seed` of the day. Games whose options have no
+    /// `seed` need to say how the seed is used instead.
+    fn daily(seed: u64) -> Value {
+        json!({ "seed": seed })
+    }
+
+    /// Registers routes specific to this game, relative to the scope of the game.
+    fn routes(_cfg: &mut web::ServiceConfig) {}
+}
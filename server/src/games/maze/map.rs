@@ -0,0 +1,100 @@
+use super::{Board, MazeGame};
+use crate::api::{SessionToken, Sessions};
+use crate::error::ServiceError;
+use crate::game::Game;
+use actix_web::HttpResponse;
+
+/// The /map endpoint. Returns the map associated with the session token passed into the request.
+/// Infinite mazes only return the window of tiles around the player.
+pub fn map<B: Board>(state: Sessions, token: SessionToken) -> Result<HttpResponse, ServiceError> {
+    let sessions = state.lock().unwrap();
+    let game = sessions.get(&token)?.downcast::<MazeGame<B>>()?;
+    Ok(HttpResponse::Ok().json(game.observe()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{MazeGame, INFINITE_MAP_RADIUS};
+    use crate::api::{routes, Session, SessionStore, SessionToken, Sessions};
+    use crate::maze::{GameMode, InfiniteMaze, Maze};
+    use actix_web::{http::StatusCode, test, web, App};
+    use bytes::Bytes;
+    use std::sync::Mutex;
+
+    #[test]
+    /// Return the map for the game session requested
+    fn returns_maze_associated_with_token() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let token = SessionToken::new();
+        let maze = Maze::new(9);
+
+        {
+            let mut sessions = sessions.lock().unwrap();
+            sessions
+                .insert(
+                    token,
+                    Session::new(MazeGame::new(maze.clone(), GameMode::Standard)),
+                )
+                .unwrap();
+        }
+
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let req = test::TestRequest::get()
+            .uri("/game/maze/map")
+            .header("X-TOKEN", token.to_string())
+            .to_request();
+
+        let response = test::read_response(&mut app, req);
+
+        assert_eq!(response, Bytes::from(serde_json::to_string(&maze).unwrap()));
+    }
+
+    #[test]
+    /// Return only the window around the player for infinite mazes
+    fn returns_window_of_infinite_maze() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let token = SessionToken::new();
+        let maze = InfiniteMaze::new(1, 100);
+
+        {
+            let mut sessions = sessions.lock().unwrap();
+            sessions
+                .insert(
+                    token,
+                    Session::new(MazeGame::new(maze.clone(), GameMode::Standard)),
+                )
+                .unwrap();
+        }
+
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let req = test::TestRequest::get()
+            .uri("/game/infinite-maze/map")
+            .header("X-TOKEN", token.to_string())
+            .to_request();
+
+        let response = test::read_response(&mut app, req);
+
+        assert_eq!(
+            response,
+            Bytes::from(serde_json::to_string(&maze.window(INFINITE_MAP_RADIUS)).unwrap())
+        );
+    }
+
+    #[test]
+    /// Return a 404 if the session for the given token does not exist
+    fn returns_404_with_valid_token() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::get()
+            .uri("/game/maze/map")
+            .header("X-TOKEN", SessionToken::new().to_string())
+            .to_request();
+
+        let response = test::call_service(&mut app, req);
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
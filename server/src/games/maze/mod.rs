@@ -0,0 +1,456 @@
+use crate::error::ServiceError;
+use crate::game::{Event, Game, NewGame};
+use crate::maze::{
+    Direction, DirectionBlocked, GameMode, GameStats, GameStatus, InfiniteMaze, LocalView, Maze,
+    MovesApplied, NeighbouringTileTypes, Play, Probe, Score, ScoreInputs, ScoringFormula, Turn,
+};
+use actix_web::web;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use start::{InfiniteOptions, StartOptions};
+use std::any::Any;
+use std::time::{Duration, Instant};
+
+mod map;
+mod move_player;
+mod moves;
+mod start;
+mod verify;
+mod view;
+
+/// How many tiles around the player the map of an infinite maze shows in each direction.
+const INFINITE_MAP_RADIUS: usize = 10;
+
+/// The operations the maze game needs from a maze, implemented by both the bounded `Maze` and the
+/// `InfiniteMaze`.
+pub trait Board: Send + 'static {
+    type Position: Serialize;
+
+    fn move_player(&mut self, direction: Direction) -> Result<(), DirectionBlocked>;
+    fn apply_moves(&mut self, directions: &[Direction]) -> MovesApplied<Self::Position>;
+    fn neighbouring_tile_types(&self) -> NeighbouringTileTypes;
+    fn look(&mut self) -> Probe;
+    fn local_view(&self, radius: usize) -> LocalView;
+    fn status(&self) -> GameStatus;
+    fn player(&self) -> Self::Position;
+    fn stats(&self) -> GameStats;
+    fn score_inputs(&self, elapsed: Duration) -> ScoreInputs;
+
+    /// The map returned by /map.
+    fn map(&self) -> Value;
+}
+
+/// A game of finding the exit of a maze.
+pub struct MazeGame<B> {
+    board: B,
+    started: Instant,
+    scoring: Box<dyn ScoringFormula + Send>,
+    score: Option<Score>,
+    config: Value,
+    /// Every turn that changed the game, recorded with when it was taken and how far the player
+    /// got so the game can be played back exactly.
+    history: Vec<Turn>,
+    /// What happened since the events were last taken.
+    events: Vec<Event>,
+}
+
+/// A recorded game as returned by /replay, which `maze::Replay` reads back for bounded mazes.
+#[derive(Debug, Serialize)]
+struct Replay<'a> {
+    config: &'a Value,
+    turns: &'a [Turn],
+}
+
+/// A saved game: the settings it was started with and every turn taken since.
+#[derive(Debug, Deserialize, Serialize)]
+struct Snapshot {
+    config: Value,
+    elapsed_ms: u64,
+    history: Vec<Turn>,
+}
+
+/// The actions accepted by /act, such as `{"move": "up"}`, `{"moves": ["right 3"]}` or `"look"`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Action {
+    Move(Direction),
+    Moves(Vec<String>),
+    Look,
+}
+
+impl<B: Board> MazeGame<B> {
+    pub fn new(board: B, mode: GameMode) -> Self {
+        MazeGame {
+            board,
+            started: Instant::now(),
+            scoring: mode.scoring(),
+            score: None,
+            config: Value::Null,
+            history: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Sets the settings returned by /start.
+    pub fn with_config(mut self, config: impl Serialize) -> Self {
+        self.config = to_json(config);
+        self
+    }
+
+    pub fn board(&self) -> &B {
+        &self.board
+    }
+
+    pub fn move_player(
+        &mut self,
+        direction: Direction,
+    ) -> Result<move_player::MoveResponse, ServiceError> {
+        let before = self.board.stats();
+        let at = self.record(Play::Move(direction));
+        let moved = self.board.move_player(direction);
+        self.end_turn(at, before, Some(direction).filter(|_| moved.is_err()));
+        moved?;
+        Ok(move_player::MoveResponse {
+            neighbours: self.board.neighbouring_tile_types(),
+            status: self.board.status(),
+            score: self.score,
+        })
+    }
+
+    pub fn apply_moves(&mut self, directions: &[Direction]) -> moves::Response<B::Position> {
+        let before = self.board.stats();
+        let at = self.record(Play::Moves(directions.to_vec()));
+        let applied = self.board.apply_moves(directions);
+        let blocked = directions.get(applied.moved).filter(|_| applied.blocked);
+        self.end_turn(at, before, blocked.copied());
+        moves::Response {
+            applied,
+            neighbours: self.board.neighbouring_tile_types(),
+            status: self.board.status(),
+            score: self.score,
+        }
+    }
+
+    pub fn look(&mut self) -> Probe {
+        let before = self.board.stats();
+        let at = self.record(Play::Look);
+        let probe = self.board.look();
+        self.end_turn(at, before, None);
+        probe
+    }
+
+    /// Adds a turn taken now to the history and returns how far into the game it was taken.
+    fn record(&mut self, play: Play) -> Duration {
+        let at = self.started.elapsed();
+        self.history.push(Turn {
+            at_ms: at.as_millis() as u64,
+            play,
+            moved: 0,
+        });
+        at
+    }
+
+    /// Finishes a turn taken at `at` into the game, which started from the `before` stats and was
+    /// stopped by a wall in the `blocked` direction if any, and adds the events of the turn.
+    fn end_turn(&mut self, at: Duration, before: GameStats, blocked: Option<Direction>) {
+        let after = self.board.stats();
+        if let Some(turn) = self.history.last_mut() {
+            turn.moved = after.moves - before.moves;
+        }
+        if after.moves > before.moves {
+            self.events.push(Event::Moved {
+                position: to_json(self.board.player()),
+                steps: after.moves - before.moves,
+            });
+        }
+        if let Some(direction) = blocked {
+            self.events.push(Event::Blocked { direction });
+        }
+        if after.tiles_revealed > before.tiles_revealed {
+            self.events.push(Event::Revealed {
+                tiles: after.tiles_revealed - before.tiles_revealed,
+            });
+        }
+
+        let scored = self.score.is_some();
+        self.check_finished(at);
+        if let (false, Some(score)) = (scored, self.score) {
+            self.events.push(Event::Won { score });
+        }
+    }
+
+    /// Scores the game the first time it is found to be won at `at` into the game, so the elapsed
+    /// time is taken when the exit was reached.
+    fn check_finished(&mut self, at: Duration) {
+        if self.score.is_none() && self.board.status() == GameStatus::Won {
+            let inputs = self.board.score_inputs(at);
+            self.score = Some(inputs.score(self.scoring.as_ref()));
+        }
+    }
+
+    /// Rebuilds a saved game by starting it with the saved settings and taking every saved turn
+    /// again, at the time it was originally taken.
+    fn restore_with(
+        snapshot: Value,
+        start: impl FnOnce(Value) -> Result<Self, ServiceError>,
+    ) -> Result<Self, ServiceError> {
+        let snapshot: Snapshot = serde_json::from_value(snapshot)
+            .map_err(|error| ServiceError::InvalidOptions(error.to_string()))?;
+        let mut game = start(snapshot.config)?;
+
+        for turn in snapshot.history {
+            match &turn.play {
+                Play::Move(direction) => {
+                    // Blocked moves still count towards the score, so they are kept in the history.
+                    let _ = game.board.move_player(*direction);
+                }
+                Play::Moves(directions) => {
+                    game.board.apply_moves(directions);
+                }
+                Play::Look => {
+                    game.board.look();
+                }
+            }
+            game.check_finished(Duration::from_millis(turn.at_ms));
+            game.history.push(turn);
+        }
+
+        let elapsed = Duration::from_millis(snapshot.elapsed_ms);
+        game.started = Instant::now()
+            .checked_sub(elapsed)
+            .unwrap_or_else(Instant::now);
+        Ok(game)
+    }
+}
+
+impl<B: Board> Game for MazeGame<B> {
+    fn act(&mut self, action: Value) -> Result<Value, ServiceError> {
+        let action: Action = serde_json::from_value(action)
+            .map_err(|error| ServiceError::InvalidAction(error.to_string()))?;
+
+        Ok(match action {
+            Action::Move(direction) => to_json(self.move_player(direction)?),
+            Action::Moves(commands) => to_json(self.apply_moves(&moves::parse_moves(&commands)?)),
+            Action::Look => to_json(self.look()),
+        })
+    }
+
+    fn observe(&self) -> Value {
+        self.board.map()
+    }
+
+    fn status(&self) -> GameStatus {
+        self.board.status()
+    }
+
+    fn score(&self) -> Option<Score> {
+        self.score
+    }
+
+    fn config(&self) -> Value {
+        self.config.clone()
+    }
+
+    fn snapshot(&self) -> Value {
+        to_json(Snapshot {
+            config: self.config.clone(),
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            history: self.history.clone(),
+        })
+    }
+
+    fn replay(&self) -> Value {
+        to_json(Replay {
+            config: &self.config,
+            turns: &self.history,
+        })
+    }
+
+    fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl NewGame for MazeGame<Maze> {
+    const NAME: &'static str = "maze";
+
+    type Options = StartOptions;
+
+    fn start(options: StartOptions) -> Result<Self, ServiceError> {
+        options.build()
+    }
+
+    fn restore(snapshot: Value) -> Result<Self, ServiceError> {
+        MazeGame::restore_with(snapshot, |config| Self::start(options_from(config)?))
+    }
+
+    fn routes(cfg: &mut web::ServiceConfig) {
+        routes::<Maze>(cfg);
+        cfg.service(
+            web::resource("/verify")
+                .data(web::JsonConfig::default().limit(verify::MAX_SUBMISSION_BYTES))
+                .route(web::post().to(verify::verify)),
+        );
+    }
+}
+
+impl NewGame for MazeGame<InfiniteMaze> {
+    const NAME: &'static str = "infinite-maze";
+
+    type Options = InfiniteOptions;
+
+    fn start(options: InfiniteOptions) -> Result<Self, ServiceError> {
+        options.build()
+    }
+
+    fn restore(snapshot: Value) -> Result<Self, ServiceError> {
+        MazeGame::restore_with(snapshot, |config| Self::start(options_from(config)?))
+    }
+
+    fn routes(cfg: &mut web::ServiceConfig) {
+        routes::<InfiniteMaze>(cfg);
+    }
+}
+
+/// Registers the routes shared by both kinds of maze.
+fn routes<B: Board>(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/map").route(web::get().to(map::map::<B>)));
+    cfg.service(web::resource("/look").route(web::post().to(move_player::look::<B>)));
+    cfg.service(web::resource("/view").route(web::get().to(view::local_view::<B>)));
+    cfg.service(
+        web::resource("/move").route(web::get().to(move_player::neighbouring_tile_types::<B>)),
+    );
+    cfg.service(
+        web::resource("/move/{direction}").route(web::post().to(move_player::move_player::<B>)),
+    );
+    cfg.service(web::resource("/moves").route(web::post().to(moves::apply_moves::<B>)));
+}
+
+/// Reads the settings of a saved game back as the options to start it with.
+fn options_from<O: DeserializeOwned>(config: Value) -> Result<O, ServiceError> {
+    serde_json::from_value(config).map_err(|error| ServiceError::InvalidOptions(error.to_string()))
+}
+
+fn to_json(value: impl Serialize) -> Value {
+    serde_json::to_value(value).expect("maze responses serialize to json")
+}
+
+/// A 3x3 maze and the moves that take the player from its start to its exit, for tests that need
+/// to win a game.
+#[cfg(test)]
+pub fn solved_maze() -> (crate::maze::MazeConfig, [Direction; 4]) {
+    use crate::maze::{Generator, MazeConfig, Visibility};
+
+    let config = MazeConfig {
+        width: 3,
+        height: 3,
+        seed: 1,
+        generator: Generator::Kruskal,
+        visibility: Visibility::Fog,
+    };
+    let route = [
+        Direction::Right,
+        Direction::Right,
+        Direction::Down,
+        Direction::Down,
+    ];
+    (config, route)
+}
+
+impl Board for Maze {
+    type Position = crate::maze::Position;
+
+    fn move_player(&mut self, direction: Direction) -> Result<(), DirectionBlocked> {
+        Maze::move_player(self, direction)
+    }
+
+    fn apply_moves(&mut self, directions: &[Direction]) -> MovesApplied<Self::Position> {
+        Maze::apply_moves(self, directions)
+    }
+
+    fn neighbouring_tile_types(&self) -> NeighbouringTileTypes {
+        Maze::neighbouring_tile_types(self)
+    }
+
+    fn look(&mut self) -> Probe {
+        Maze::look(self)
+    }
+
+    fn local_view(&self, radius: usize) -> LocalView {
+        Maze::local_view(self, radius)
+    }
+
+    fn status(&self) -> GameStatus {
+        Maze::status(self)
+    }
+
+    fn player(&self) -> Self::Position {
+        Maze::player(self)
+    }
+
+    fn stats(&self) -> GameStats {
+        Maze::stats(self)
+    }
+
+    fn score_inputs(&self, elapsed: Duration) -> ScoreInputs {
+        Maze::score_inputs(self, elapsed)
+    }
+
+    fn map(&self) -> Value {
+        to_json(self)
+    }
+}
+
+impl Board for InfiniteMaze {
+    type Position = crate::maze::WorldPosition;
+
+    fn move_player(&mut self, direction: Direction) -> Result<(), DirectionBlocked> {
+        InfiniteMaze::move_player(self, direction)
+    }
+
+    fn apply_moves(&mut self, directions: &[Direction]) -> MovesApplied<Self::Position> {
+        InfiniteMaze::apply_moves(self, directions)
+    }
+
+    fn neighbouring_tile_types(&self) -> NeighbouringTileTypes {
+        InfiniteMaze::neighbouring_tile_types(self)
+    }
+
+    fn look(&mut self) -> Probe {
+        InfiniteMaze::look(self)
+    }
+
+    fn local_view(&self, radius: usize) -> LocalView {
+        InfiniteMaze::local_view(self, radius)
+    }
+
+    fn status(&self) -> GameStatus {
+        InfiniteMaze::status(self)
+    }
+
+    fn player(&self) -> Self::Position {
+        InfiniteMaze::player(self)
+    }
+
+    fn stats(&self) -> GameStats {
+        InfiniteMaze::stats(self)
+    }
+
+    fn score_inputs(&self, elapsed: Duration) -> ScoreInputs {
+        InfiniteMaze::score_inputs(self, elapsed)
+    }
+
+    /// Only the window of tiles around the player, as the map has no edges.
+    fn map(&self) -> Value {
+        to_json(self.window(INFINITE_MAP_RADIUS))
+    }
+}
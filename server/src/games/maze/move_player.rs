@@ -0,0 +1,178 @@
+use super::{Board, MazeGame};
+use crate::api::{SessionToken, Sessions};
+use crate::error::ServiceError;
+use crate::maze::{Direction, GameStatus, NeighbouringTileTypes, Score};
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct MoveResponse {
+    #[serde(flatten)]
+    pub neighbours: NeighbouringTileTypes,
+    pub status: GameStatus,
+    /// Only present once the player has reached the exit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<Score>,
+}
+
+/// Moves a player in one direction if the path is not blocked and returns the tile_types of the directions the player can move to.
+/// Once the player reaches the exit the response also contains the score for the game.
+pub fn move_player<B: Board>(
+    direction: web::Path<Direction>,
+    state: Sessions,
+    token: SessionToken,
+) -> Result<HttpResponse, ServiceError> {
+    let mut sessions = state.lock().unwrap();
+    let mut session = sessions.get_mut(&token)?;
+    let game = session.downcast_mut::<MazeGame<B>>()?;
+
+    Ok(HttpResponse::Ok().json(game.move_player(*direction)?))
+}
+
+/// Looks down every corridor leading away from the player, revealing the tiles along them, and
+/// returns how many open tiles lie in each direction before the next wall.
+pub fn look<B: Board>(state: Sessions, token: SessionToken) -> Result<HttpResponse, ServiceError> {
+    let mut sessions = state.lock().unwrap();
+    let mut session = sessions.get_mut(&token)?;
+    let game = session.downcast_mut::<MazeGame<B>>()?;
+    Ok(HttpResponse::Ok().json(game.look()))
+}
+
+/// Returns the types for each tile that neighbours the players current position.
+pub fn neighbouring_tile_types<B: Board>(
+    state: Sessions,
+    token: SessionToken,
+) -> Result<HttpResponse, ServiceError> {
+    let sessions = state.lock().unwrap();
+    let game = sessions.get(&token)?.downcast::<MazeGame<B>>()?;
+    Ok(HttpResponse::Ok().json(game.board().neighbouring_tile_types()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{solved_maze, MazeGame};
+    use crate::api::{routes, Session, SessionStore, SessionToken, Sessions};
+    use crate::maze::{CornerCutting, GameMode, Maze, Movement};
+    use actix_web::{http::StatusCode, test, web, App};
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    #[test]
+    /// Looking reports the cost set for the session
+    fn look_reports_probe_cost() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let token = SessionToken::new();
+        let mut maze = Maze::new(9);
+        maze.set_probe_cost(5);
+
+        {
+            let mut sessions = sessions.lock().unwrap();
+            sessions
+                .insert(token, Session::new(MazeGame::new(maze, GameMode::Standard)))
+                .unwrap();
+        }
+
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::post()
+            .uri("/game/maze/look")
+            .header("X-TOKEN", token.to_string())
+            .to_request();
+
+        let response: serde_json::Value = test::read_response_json(&mut app, req);
+
+        assert_eq!(response["cost"], 5);
+        assert_eq!(response["up"], 0);
+        assert_eq!(response["left"], 0);
+    }
+
+    #[test]
+    /// Spectators can see the neighbouring tiles but are forbidden from moving the player
+    fn spectator_cannot_move() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let session = Session::new(MazeGame::new(Maze::new(9), GameMode::Standard));
+        let spectator = session.spectator();
+        sessions
+            .lock()
+            .unwrap()
+            .insert(SessionToken::new(), session)
+            .unwrap();
+
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::get()
+            .uri("/game/maze/move")
+            .header("X-TOKEN", spectator.to_string())
+            .to_request();
+        assert_eq!(test::call_service(&mut app, req).status(), StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .uri("/game/maze/move/down")
+            .header("X-TOKEN", spectator.to_string())
+            .to_request();
+        assert_eq!(
+            test::call_service(&mut app, req).status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[test]
+    /// Reaching the exit ends the game and returns its score
+    fn winning_move_returns_score() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let token = SessionToken::new();
+        let (config, route) = solved_maze();
+        let maze = config.generate().unwrap();
+
+        {
+            let mut sessions = sessions.lock().unwrap();
+            sessions
+                .insert(token, Session::new(MazeGame::new(maze, GameMode::Standard)))
+                .unwrap();
+        }
+
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let mut response = serde_json::Value::Null;
+        for direction in route.iter() {
+            let req = test::TestRequest::post()
+                .uri(&format!(
+                    "/game/maze/move/{}",
+                    json!(direction).as_str().unwrap()
+                ))
+                .header("X-TOKEN", token.to_string())
+                .to_request();
+            response = test::read_response_json(&mut app, req);
+        }
+
+        assert_eq!(response["status"], "won");
+        assert_eq!(response["score"]["moves"], 4);
+        assert_eq!(response["score"]["optimal_moves"], 4);
+        assert_eq!(response["score"]["blocked_moves"], 0);
+    }
+
+    #[test]
+    /// Diagonal directions are parsed from their hyphenated names. The player starts in the top
+    /// left corner, so moving up and to the left is always blocked.
+    fn diagonal_direction_from_path() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let token = SessionToken::new();
+        let mut maze = Maze::new(9);
+        maze.set_movement(Movement::Diagonal);
+        maze.set_corner_cutting(CornerCutting::Always);
+
+        {
+            let mut sessions = sessions.lock().unwrap();
+            sessions
+                .insert(token, Session::new(MazeGame::new(maze, GameMode::Standard)))
+                .unwrap();
+        }
+
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::post()
+            .uri("/game/maze/move/up-left")
+            .header("X-TOKEN", token.to_string())
+            .to_request();
+
+        let response = test::call_service(&mut app, req);
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,159 @@
+use super::{Board, MazeGame};
+use crate::api::{SessionToken, Sessions};
+use crate::error::ServiceError;
+use crate::maze::{Direction, GameStatus, MovesApplied, NeighbouringTileTypes, Score};
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+
+/// The most moves a single request to /moves may expand to.
+const MAX_MOVES: usize = 1000;
+
+#[derive(Debug, Serialize)]
+pub struct Response<P> {
+    #[serde(flatten)]
+    pub applied: MovesApplied<P>,
+    pub neighbours: NeighbouringTileTypes,
+    pub status: GameStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<Score>,
+}
+
+/// The /moves endpoint. Takes a list of moves, each a direction optionally followed by a repeat
+/// count such as `"right 5"`, and moves the player until the first blocked move. Returns how many
+/// moves were taken, the tiles revealed on the way and the tile types now neighbouring the player,
+/// along with the score once the exit has been reached.
+pub fn apply_moves<B: Board>(
+    commands: web::Json<Vec<String>>,
+    state: Sessions,
+    token: SessionToken,
+) -> Result<HttpResponse, ServiceError> {
+    let directions = parse_moves(&commands)?;
+
+    let mut sessions = state.lock().unwrap();
+    let mut session = sessions.get_mut(&token)?;
+    let game = session.downcast_mut::<MazeGame<B>>()?;
+
+    Ok(HttpResponse::Ok().json(game.apply_moves(&directions)))
+}
+
+/// Expands the move commands into the individual moves they stand for.
+pub fn parse_moves(commands: &[String]) -> Result<Vec<Direction>, ServiceError> {
+    parse_moves_up_to(commands, MAX_MOVES)
+}
+
+/// Expands the move commands like `parse_moves`, allowing at most `max` moves.
+pub fn parse_moves_up_to(commands: &[String], max: usize) -> Result<Vec<Direction>, ServiceError> {
+    let mut directions = Vec::new();
+
+    for command in commands {
+        let invalid = || ServiceError::InvalidMoveCommand(command.clone());
+        let mut parts = command.split_whitespace();
+
+        let direction: Direction = parts
+            .next()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?;
+        let count = match parts.next() {
+            Some(count) => count.parse::<usize>().map_err(|_| invalid())?,
+            None => 1,
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        if count > max - directions.len() {
+            return Err(ServiceError::TooManyMoves { max });
+        }
+        directions.extend((0..count).map(|_| direction));
+    }
+
+    Ok(directions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MazeGame;
+    use super::{parse_moves, MAX_MOVES};
+    use crate::api::{routes, Session, SessionStore, SessionToken, Sessions};
+    use crate::error::ServiceError;
+    use crate::maze::{Direction, GameMode, Maze};
+    use actix_web::{test, web, App};
+    use serde::Deserialize;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Deserialize)]
+    struct Response {
+        moved: usize,
+        blocked: bool,
+    }
+
+    fn commands(commands: &[&str]) -> Vec<String> {
+        commands.iter().map(|command| command.to_string()).collect()
+    }
+
+    #[test]
+    /// Commands expand into single moves, repeating those with a count
+    fn run_length_commands_expand() {
+        assert_eq!(
+            parse_moves(&commands(&["up", "right 3", "down-left 0"])),
+            Ok(vec![
+                Direction::Up,
+                Direction::Right,
+                Direction::Right,
+                Direction::Right
+            ])
+        );
+    }
+
+    #[test]
+    /// Commands that are not a direction and an optional count are rejected
+    fn invalid_commands_rejected() {
+        for &command in &["", "sideways", "up two", "up 2 3", "left -1"] {
+            assert_eq!(
+                parse_moves(&commands(&[command])),
+                Err(ServiceError::InvalidMoveCommand(command.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    /// The total number of moves in a request is limited
+    fn too_many_moves_rejected() {
+        let too_many = format!("up {}", MAX_MOVES + 1);
+        assert_eq!(
+            parse_moves(&commands(&["up", &too_many])),
+            Err(ServiceError::TooManyMoves { max: MAX_MOVES })
+        );
+    }
+
+    #[test]
+    /// The moves stop at the first blocked one. The player starts in the top left corner, so
+    /// moving up is always blocked.
+    fn stops_at_first_blocked_move() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let token = SessionToken::new();
+
+        {
+            let mut sessions = sessions.lock().unwrap();
+            sessions
+                .insert(
+                    token,
+                    Session::new(MazeGame::new(Maze::new(9), GameMode::Standard)),
+                )
+                .unwrap();
+        }
+
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::post()
+            .uri("/game/maze/moves")
+            .header("X-TOKEN", token.to_string())
+            .set_json(&["up", "right 4"])
+            .to_request();
+
+        let response: Response = test::read_response_json(&mut app, req);
+
+        assert_eq!(response.moved, 0);
+        assert!(response.blocked);
+    }
+}
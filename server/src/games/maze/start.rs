@@ -0,0 +1,293 @@
+use super::MazeGame;
+use crate::error::ServiceError;
+use crate::maze::{
+    CornerCutting, GameMode, Generator, InfiniteMaze, Maze, MazeConfig, Movement, Visibility,
+    MAX_EXIT_DISTANCE,
+};
+use serde::{Deserialize, Serialize};
+
+/// The distance of the exit from the start of an infinite maze if none is requested.
+const DEFAULT_EXIT_DISTANCE: u64 = 100;
+
+/// The width and height of a maze if none are requested.
+const DEFAULT_SIZE: usize = 9;
+
+/// The largest width or height of a maze that can be requested.
+const MAX_SIZE: usize = 1001;
+
+/// The highest cost of a probe that can be requested. A probe costing the points of a perfect game
+/// already leaves nothing to score.
+const MAX_PROBE_COST: usize = 1000;
+
+/// The options of /game/maze/start. The `width`, `height`, `seed`, `generator` and `visibility`
+/// choose the maze and `mode` how the game is scored. Diagonal movement can be enabled with
+/// `movement` and `corner_cutting` and probing with /look can be given a cost with `probe_cost`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StartOptions {
+    width: Option<usize>,
+    height: Option<usize>,
+    seed: Option<u64>,
+    generator: Generator,
+    visibility: Visibility,
+    mode: GameMode,
+    movement: Movement,
+    corner_cutting: CornerCutting,
+    probe_cost: usize,
+}
+
+/// The settings a maze game was started with, returned by /game/maze/start. Sending them back as
+/// the options starts the same maze again.
+#[derive(Debug, Serialize)]
+struct Settings {
+    #[serde(flatten)]
+    maze: MazeConfig,
+    mode: GameMode,
+    movement: Movement,
+    corner_cutting: CornerCutting,
+    probe_cost: usize,
+}
+
+/// The options of /game/infinite-maze/start. Takes the same options as a bounded maze apart from
+/// its size and shape, and the distance of the exit can be chosen with `distance`. Games are
+/// scored on speed unless another `mode` is chosen.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InfiniteOptions {
+    seed: Option<u64>,
+    distance: Option<u64>,
+    mode: GameMode,
+    movement: Movement,
+    corner_cutting: CornerCutting,
+    probe_cost: usize,
+}
+
+/// The settings an infinite maze game was started with, returned by /game/infinite-maze/start.
+#[derive(Debug, Serialize)]
+struct InfiniteSettings {
+    seed: u64,
+    distance: u64,
+    mode: GameMode,
+    movement: Movement,
+    corner_cutting: CornerCutting,
+    probe_cost: usize,
+}
+
+impl StartOptions {
+    pub fn build(&self) -> Result<MazeGame<Maze>, ServiceError> {
+        check_probe_cost(self.probe_cost)?;
+        let settings = Settings {
+            maze: MazeConfig {
+                width: self.width.unwrap_or(DEFAULT_SIZE),
+                height: self.height.unwrap_or(DEFAULT_SIZE),
+                seed: self.seed.unwrap_or_else(rand::random),
+                generator: self.generator,
+                visibility: self.visibility,
+            },
+            mode: self.mode,
+            movement: self.movement,
+            corner_cutting: self.corner_cutting,
+            probe_cost: self.probe_cost,
+        };
+
+        let mut maze = generate(&settings.maze)?;
+        maze.set_movement(settings.movement);
+        maze.set_corner_cutting(settings.corner_cutting);
+        maze.set_probe_cost(settings.probe_cost);
+        Ok(MazeGame::new(maze, settings.mode).with_config(settings))
+    }
+}
+
+/// Generates the maze of `config`, refusing mazes too large to be played. The tiles are stored
+/// compactly, as the server keeps thousands of mazes in memory at once.
+pub fn generate(config: &MazeConfig) -> Result<Maze, ServiceError> {
+    if config.width > MAX_SIZE || config.height > MAX_SIZE {
+        return Err(ServiceError::InvalidOptions(format!(
+            "the width and height of a maze can be at most {}",
+            MAX_SIZE
+        )));
+    }
+    Ok(config.generate_compact()?)
+}
+
+fn check_probe_cost(probe_cost: usize) -> Result<(), ServiceError> {
+    if probe_cost > MAX_PROBE_COST {
+        return Err(ServiceError::InvalidOptions(format!(
+            "the probe cost can be at most {}",
+            MAX_PROBE_COST
+        )));
+    }
+    Ok(())
+}
+
+impl Default for InfiniteOptions {
+    fn default() -> Self {
+        InfiniteOptions {
+            seed: None,
+            distance: None,
+            mode: GameMode::Speed,
+            movement: Movement::default(),
+            corner_cutting: CornerCutting::default(),
+            probe_cost: 0,
+        }
+    }
+}
+
+impl InfiniteOptions {
+    pub fn build(&self) -> Result<MazeGame<InfiniteMaze>, ServiceError> {
+        check_probe_cost(self.probe_cost)?;
+        if self
+            .distance
+            .map_or(false, |distance| distance > MAX_EXIT_DISTANCE)
+        {
+            return Err(ServiceError::InvalidOptions(format!(
+                "the distance of the exit can be at most {}",
+                MAX_EXIT_DISTANCE
+            )));
+        }
+
+        let settings = InfiniteSettings {
+            seed: self.seed.unwrap_or_else(rand::random),
+            distance: self.distance.unwrap_or(DEFAULT_EXIT_DISTANCE),
+            mode: self.mode,
+            movement: self.movement,
+            corner_cutting: self.corner_cutting,
+            probe_cost: self.probe_cost,
+        };
+
+        let mut maze = InfiniteMaze::new(settings.seed, settings.distance);
+        maze.set_movement(settings.movement);
+        maze.set_corner_cutting(settings.corner_cutting);
+        maze.set_probe_cost(settings.probe_cost);
+        Ok(MazeGame::new(maze, settings.mode).with_config(settings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MazeGame;
+    use crate::api::{routes, SessionStore, SessionToken, Sessions};
+    use crate::maze::{InfiniteMaze, Maze, Movement};
+    use actix_web::{http::StatusCode, test, web, App};
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    #[derive(Debug, Deserialize)]
+    struct Response {
+        token: SessionToken,
+        config: Value,
+    }
+
+    #[test]
+    /// Starting an infinite session stores an unbounded maze built from the requested seed
+    fn infinite_session_uses_requested_seed() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let req = test::TestRequest::post()
+            .uri("/game/infinite-maze/start")
+            .set_json(&json!({ "seed": 42, "distance": 10, "movement": "diagonal" }))
+            .to_request();
+
+        let response: Response = test::read_response_json(&mut app, req);
+
+        let sessions = sessions.lock().unwrap();
+        let game = sessions
+            .get(&response.token)
+            .unwrap()
+            .downcast::<MazeGame<InfiniteMaze>>()
+            .unwrap();
+        assert_eq!(game.board().seed(), 42);
+        assert_eq!(game.board().movement(), Movement::Diagonal);
+        assert_eq!(response.config["mode"], "speed");
+    }
+
+    #[test]
+    /// The maze is generated from the requested options and the response echoes every setting,
+    /// including the defaults that were filled in
+    fn maze_uses_requested_options() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let options = json!({
+            "width": 15,
+            "height": 7,
+            "seed": 3,
+            "generator": "backtracker",
+            "visibility": "revealed"
+        });
+        let req = test::TestRequest::post()
+            .uri("/game/maze/start")
+            .set_json(&options)
+            .to_request();
+
+        let response: Response = test::read_response_json(&mut app, req);
+
+        assert_eq!(
+            response.config,
+            json!({
+                "width": 15,
+                "height": 7,
+                "seed": 3,
+                "generator": "backtracker",
+                "visibility": "revealed",
+                "mode": "standard",
+                "movement": "orthogonal",
+                "corner_cutting": "never",
+                "probe_cost": 0
+            })
+        );
+
+        let sessions = sessions.lock().unwrap();
+        let maze = sessions
+            .get(&response.token)
+            .unwrap()
+            .downcast::<MazeGame<Maze>>()
+            .unwrap()
+            .board();
+        assert_eq!((maze.width(), maze.height()), (15, 7));
+        assert!(maze.is_compact());
+        assert!(maze.tiles().iter().all(|&tile| tile != 0));
+    }
+
+    #[test]
+    /// Sizes that cannot be generated and options the maze does not know are rejected
+    fn invalid_options_rejected() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+
+        for options in &[
+            json!({ "width": 8 }),
+            json!({ "height": 0 }),
+            json!({ "width": 100_001 }),
+            json!({ "probe_cost": 1_000_000 }),
+            json!({ "generator": "prim" }),
+            json!({ "colour": "red" }),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/game/maze/start")
+                .set_json(options)
+                .to_request();
+
+            let response = test::call_service(&mut app, req);
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", options);
+        }
+    }
+
+    #[test]
+    /// Exits too far away to be placed are rejected
+    fn infinite_distance_limited() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::post()
+            .uri("/game/infinite-maze/start")
+            .set_json(&json!({ "distance": u64::MAX }))
+            .to_request();
+
+        let response = test::call_service(&mut app, req);
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
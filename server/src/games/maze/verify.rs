@@ -0,0 +1,142 @@
+use super::moves::parse_moves_up_to;
+use super::start::generate;
+use crate::error::ServiceError;
+use crate::maze::{GameMode, GameStatus, ReplayConfig, Score};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The most moves a submitted solution may expand to.
+const MAX_SUBMITTED_MOVES: usize = 1_000_000;
+
+/// The largest submission accepted, in bytes. Solutions of large mazes are much longer than the
+/// requests of a session, so /verify does not use the configured `json_limit`.
+pub const MAX_SUBMISSION_BYTES: usize = 8 * 1024 * 1024;
+
+/// A solution to a maze worked out without a session, such as
+/// `{"config": {"width": 9, "height": 9, "seed": 42}, "moves": ["right 2", "down"]}`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Submission {
+    /// The settings to generate the maze from, as returned by /start. Settings that do not change
+    /// the maze or how the player moves through it are ignored.
+    config: ReplayConfig,
+    /// Every move from the start to the exit, in the same format as /moves.
+    moves: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Verified {
+    config: ReplayConfig,
+    score: Score,
+}
+
+/// The /verify endpoint of the bounded maze. Generates the maze of the submitted config, plays the
+/// moves in it and returns the score if they reach the exit. Solutions are scored as standard
+/// games without any penalty for time, as the time taken to find them cannot be verified.
+pub fn verify(submission: web::Json<Submission>) -> Result<HttpResponse, ServiceError> {
+    let directions = parse_moves_up_to(&submission.moves, MAX_SUBMITTED_MOVES)?;
+    let config = submission.config;
+
+    let mut maze = generate(&config.maze)?;
+    maze.set_movement(config.movement);
+    maze.set_corner_cutting(config.corner_cutting);
+    maze.set_probe_cost(config.probe_cost);
+
+    let applied = maze.apply_moves(&directions);
+    if applied.blocked {
+        return Err(ServiceError::SolutionRejected(format!(
+            "move {} runs into a wall",
+            applied.moved + 1
+        )));
+    }
+    if maze.status() != GameStatus::Won {
+        return Err(ServiceError::SolutionRejected(
+            "the moves end before the exit".to_string(),
+        ));
+    }
+
+    let score = maze
+        .score_inputs(Duration::from_secs(0))
+        .score(GameMode::Standard.scoring().as_ref());
+    Ok(HttpResponse::Ok().json(Verified { config, score }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::solved_maze;
+    use super::*;
+    use crate::api::{routes, SessionStore, Sessions};
+    use actix_web::{http::StatusCode, test, App};
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    /// Posts `submission` to /verify of a server that limits other requests to 4096 bytes, and
+    /// returns the status and body of the response.
+    fn submit(submission: Value) -> (StatusCode, Vec<u8>) {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let mut app = test::init_service(
+            App::new()
+                .register_data(sessions)
+                .data(web::JsonConfig::default().limit(4096))
+                .configure(routes),
+        );
+        let req = test::TestRequest::post()
+            .uri("/game/maze/verify")
+            .set_json(&submission)
+            .to_request();
+        let response = test::call_service(&mut app, req);
+        let status = response.status();
+        (status, test::read_body(response).to_vec())
+    }
+
+    /// The body of a submission that was rejected.
+    fn rejected(submission: Value) -> String {
+        let (status, body) = submit(submission);
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        String::from_utf8(body).unwrap()
+    }
+
+    #[test]
+    /// Moves that reach the exit are scored without a session
+    fn solution_scored() {
+        let (config, route) = solved_maze();
+
+        let (status, body) = submit(json!({ "config": config, "moves": route }));
+        let verified: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+
+        assert_eq!(verified["config"]["seed"], 1);
+        assert_eq!(verified["score"]["moves"], 4);
+        assert_eq!(verified["score"]["optimal_moves"], 4);
+        assert_eq!(verified["score"]["elapsed_ms"], 0);
+        assert!(verified["score"]["points"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    /// Moves that run into a wall or stop short of the exit are rejected
+    fn wrong_solutions_rejected() {
+        let (config, route) = solved_maze();
+
+        let blocked = rejected(json!({ "config": config, "moves": ["up"] }));
+        let unfinished = rejected(json!({ "config": config, "moves": &route[..2] }));
+
+        assert_eq!(blocked, "solution rejected: move 1 runs into a wall");
+        assert_eq!(
+            unfinished,
+            "solution rejected: the moves end before the exit"
+        );
+    }
+
+    #[test]
+    /// Submissions longer than the limit of other requests are still read
+    fn long_submissions_read() {
+        let (config, _) = solved_maze();
+        let moves = vec!["up"; 2000];
+
+        let blocked = rejected(json!({ "config": config, "moves": moves }));
+
+        assert_eq!(blocked, "solution rejected: move 1 runs into a wall");
+    }
+}
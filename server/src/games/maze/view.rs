@@ -0,0 +1,98 @@
+use super::{Board, MazeGame};
+use crate::api::{SessionToken, Sessions};
+use crate::error::ServiceError;
+use crate::maze::MAX_VIEW_RADIUS;
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+/// The radius of the view if none is requested, giving the same 5x5 tiles around the player for
+/// every request.
+const DEFAULT_VIEW_RADIUS: usize = 2;
+
+#[derive(Debug, Deserialize)]
+pub struct ViewOptions {
+    radius: Option<usize>,
+}
+
+/// The /view endpoint. Returns the square of tiles centred on the player, `radius` tiles in each
+/// direction. Tiles the player has not seen yet are hidden.
+pub fn local_view<B: Board>(
+    options: web::Query<ViewOptions>,
+    state: Sessions,
+    token: SessionToken,
+) -> Result<HttpResponse, ServiceError> {
+    let radius = options.radius.unwrap_or(DEFAULT_VIEW_RADIUS);
+    if radius > MAX_VIEW_RADIUS {
+        return Err(ServiceError::ViewRadiusTooLarge {
+            max: MAX_VIEW_RADIUS,
+        });
+    }
+
+    let sessions = state.lock().unwrap();
+    let game = sessions.get(&token)?.downcast::<MazeGame<B>>()?;
+    Ok(HttpResponse::Ok().json(game.board().local_view(radius)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MazeGame;
+    use crate::api::{routes, Session, SessionStore, SessionToken, Sessions};
+    use crate::maze::{GameMode, Maze};
+    use actix_web::{http::StatusCode, test, web, App};
+    use bytes::Bytes;
+    use std::sync::Mutex;
+
+    #[test]
+    /// Return the view of the requested radius around the player
+    fn returns_view_around_player() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let token = SessionToken::new();
+        let maze = Maze::new(9);
+        let view = maze.local_view(3);
+
+        {
+            let mut sessions = sessions.lock().unwrap();
+            sessions
+                .insert(token, Session::new(MazeGame::new(maze, GameMode::Standard)))
+                .unwrap();
+        }
+
+        let mut app =
+            test::init_service(App::new().register_data(sessions.clone()).configure(routes));
+        let req = test::TestRequest::get()
+            .uri("/game/maze/view?radius=3")
+            .header("X-TOKEN", token.to_string())
+            .to_request();
+
+        let response = test::read_response(&mut app, req);
+
+        assert_eq!(response, Bytes::from(serde_json::to_string(&view).unwrap()));
+    }
+
+    #[test]
+    /// Reject views larger than the server is willing to compute
+    fn rejects_too_large_radius() {
+        let sessions: Sessions = web::Data::new(Mutex::new(SessionStore::default()));
+        let token = SessionToken::new();
+
+        {
+            let mut sessions = sessions.lock().unwrap();
+            sessions
+                .insert(
+                    token,
+                    Session::new(MazeGame::new(Maze::new(9), GameMode::Standard)),
+                )
+                .unwrap();
+        }
+
+        let mut app = test::init_service(App::new().register_data(sessions).configure(routes));
+        let req = test::TestRequest::get()
+            .uri("/game/maze/view?radius=1000")
+            .header("X-TOKEN", token.to_string())
+            .to_request();
+
+        let response = test::call_service(&mut app, req);
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
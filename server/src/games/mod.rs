@@ -0,0 +1,12 @@
+use self::maze::MazeGame;
+use crate::api::Registry;
+use crate::maze::{InfiniteMaze, Maze};
+
+pub mod maze;
+
+/// Every game hosted by the server.
+pub fn registry() -> Registry {
+    Registry::default()
+        .register::<MazeGame<Maze>>()
+        .register::<MazeGame<InfiniteMaze>>()
+}
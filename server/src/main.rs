@@ -1,15 +1,56 @@
+use config::{Args, Config};
 use failure::Fail;
+use std::io;
+use std::process;
+use structopt::StructOpt;
 
+mod admin;
 mod api;
+mod config;
 mod error;
+mod game;
+mod games;
 use maze;
 
 fn main() {
-    env_logger::init();
+    let args = Args::from_args();
+    let config = match Config::load(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+    };
+
+    if args.print_config {
+        print!("{}", config.to_toml());
+        return;
+    }
+
+    if let Some(command) = &args.command {
+        if let Err(err) = admin::run(command, &config) {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    config.log_format.init_logger();
 
-    if let Err(err) = api::run_server("localhost:4000") {
+    if let Err(err) = run(&config) {
         for cause in Fail::iter_chain(&err) {
             println!("{}: {}", cause.name().unwrap_or("Error"), cause);
         }
     }
 }
+
+/// Runs the server, saving sessions to the sled database in the config if there is one and keeping
+/// them in memory otherwise.
+fn run(config: &Config) -> Result<(), io::Error> {
+    let storage: Box<dyn api::Storage> = match &config.session_db {
+        Some(path) => Box::new(api::SledStorage::open(path)?),
+        None => Box::new(api::MemoryStorage::default()),
+    };
+
+    api::run_server(config, storage)
+}
@@ -22,6 +22,15 @@ describe("Console", () => {
     expect(localStorage.getItem("mazeScript")).toEqual("this is a test");
   });
 
+  test("Clicking on online should switch to the offline backend", () => {
+    const { localVue, store } = createStore({
+      lines: [],
+    });
+    const menu = mount(Header, { localVue, store });
+    menu.find({ ref: "offline" }).trigger("click");
+    expect(store.state.offline).toBe(true);
+  });
+
   test.each(["Mac-Intel", "Linux x86_64"])(
     "If user hits ctrl + s the code should be saved to local storage",
     platform => {
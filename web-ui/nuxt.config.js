@@ -49,9 +49,10 @@ export default {
     proxy: true,
   },
   proxy: {
-    "/api/game/maze": {
+    "/api/game": {
       target: "http://localhost:4000",
-      pathRewrite: { "^/api/game/maze": "" },
+      pathRewrite: { "^/api": "" },
+      ws: true,
     },
   },
 };
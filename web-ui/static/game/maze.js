@@ -1,12 +1,31 @@
-async function sendRequest(url, token, method) {
+const offline =
+  new URLSearchParams(window.location.search).get("backend") === "offline";
+
+function badResponse(status, statusText, text) {
+  return new Error(`bad response: ${status} ${statusText} ${text}`);
+}
+
+async function sendRequest(url, token, method, body) {
+  if (offline) {
+    return offlineRequest(url, token, method, body);
+  }
+
   const headers = token ? { "X-TOKEN": token } : {};
-  const response = await fetch(`/api/game/maze/${url}`, { method, headers });
+  if (body !== undefined) {
+    headers["Content-Type"] = "application/json";
+    body = JSON.stringify(body);
+  }
+  const response = await fetch(`/api/game/maze/${url}`, {
+    method,
+    headers,
+    body,
+  });
 
   if (!response.ok) {
-    throw new Error(
-      `bad response: ${response.status} ${
-        response.statusText
-      } ${await response.text()}`,
+    throw badResponse(
+      response.status,
+      response.statusText,
+      await response.text(),
     );
   }
 
@@ -17,31 +36,217 @@ async function sendRequest(url, token, method) {
   return response.json();
 }
 
-const post = async (url, token) => sendRequest(url, token, "POST");
+// The offline backend answers the same requests as the game server with the
+// wasm build of the maze crate, so scripts behave the same on either backend.
+let engine;
+
+async function loadEngine() {
+  if (!engine) {
+    const wasm = await import("/game/pkg/sprokit_maze.js");
+    await wasm.default();
+    engine = { wasm, sessions: new Map() };
+  }
+  return engine;
+}
+
+function errorResponse(status, statusText, error, help) {
+  return badResponse(status, statusText, JSON.stringify({ error, help }));
+}
+
+async function offlineRequest(url, token, method, body) {
+  const { wasm, sessions } = await loadEngine();
+
+  if (method === "POST" && url === "start") {
+    // Only the options that shape the maze itself are supported offline.
+    const config = {
+      width: 9,
+      height: 9,
+      seed: Math.floor(Math.random() * 2 ** 32),
+      generator: "kruskal",
+      visibility: "fog",
+      ...body,
+    };
+    let maze;
+    try {
+      maze = wasm.Maze.from_config(config);
+    } catch (error) {
+      throw errorResponse(
+        400,
+        "Bad Request",
+        `invalid options: ${error}`,
+        "The options are sent to /start as a JSON object in the body. Every option may be left out.",
+      );
+    }
+    const token = `offline-${sessions.size + 1}`;
+    sessions.set(token, maze);
+    return { token, config };
+  }
+
+  if (!token) {
+    throw errorResponse(
+      400,
+      "Bad Request",
+      "missing session token",
+      "Session tokens are obtained by sending a post request to /start",
+    );
+  }
+
+  const maze = sessions.get(token);
+  if (!maze) {
+    throw errorResponse(
+      404,
+      "Not Found",
+      "session not found",
+      "No session was found for the given token. Session tokens are obtained by sending a post request to /start",
+    );
+  }
+
+  const directions = {
+    up: wasm.Direction.Up,
+    down: wasm.Direction.Down,
+    left: wasm.Direction.Left,
+    right: wasm.Direction.Right,
+  };
+  const move = url.match(/^move\/(\w+)$/);
+
+  if (method === "GET" && url === "map") {
+    return maze.map();
+  } else if (method === "GET" && url === "move") {
+    return maze.neighbours();
+  } else if (method === "POST" && move && move[1] in directions) {
+    try {
+      maze.move_player(directions[move[1]]);
+    } catch (_) {
+      throw errorResponse(
+        400,
+        "Bad Request",
+        "direction blocked",
+        "The direction was blocked, you need to pick another way.",
+      );
+    }
+    return maze.neighbours();
+  }
+
+  throw badResponse(404, "Not Found", "");
+}
+
+// Online games are played over a websocket once started, which saves a request
+// per move. Replies arrive in the order the requests were sent. If the
+// connection drops, every request still waiting for a reply fails rather than
+// waiting forever.
+class Socket {
+  constructor(token) {
+    const protocol = window.location.protocol === "https:" ? "wss:" : "ws:";
+    this.ws = new WebSocket(
+      `${protocol}//${window.location.host}/api/game/maze/ws?token=${token}`,
+    );
+    this.pending = [];
+    this.closed = undefined;
+    this.opened = new Promise((resolve, reject) => {
+      this.ws.onopen = resolve;
+      this.ws.onerror = () =>
+        reject(this.fail("could not connect to the game"));
+    });
+    // Failing before the socket opened rejects `opened` instead, so it must
+    // not be reported as unhandled.
+    this.opened.catch(() => {});
+    this.ws.onclose = () => this.fail("the connection to the game was lost");
+    this.ws.onmessage = event => {
+      // Replies can still arrive after the requests were failed by `close`.
+      if (this.pending.length === 0) {
+        return;
+      }
+      const { resolve, reject } = this.pending.shift();
+      const reply = JSON.parse(event.data);
+      if (reply.error) {
+        reject(new Error(reply.error));
+      } else {
+        resolve(reply);
+      }
+    };
+  }
+
+  async send(request) {
+    await this.opened;
+    if (this.closed) {
+      throw this.closed;
+    }
+    return new Promise((resolve, reject) => {
+      this.pending.push({ resolve, reject });
+      this.ws.send(JSON.stringify(request));
+    });
+  }
+
+  close() {
+    this.fail("the game was restarted");
+    this.ws.close();
+  }
+
+  // Rejects every request waiting for a reply, and every later one, with
+  // `message`. Returns the error, which only the first failure decides.
+  fail(message) {
+    if (!this.closed) {
+      this.closed = new Error(message);
+    }
+    for (const { reject } of this.pending.splice(0)) {
+      reject(this.closed);
+    }
+    return this.closed;
+  }
+}
+
+const post = async (url, token, body) =>
+  sendRequest(url, token, "POST", body);
 const get = async (url, token) => sendRequest(url, token, "GET");
 
 export class Maze {
-  async start() {
-    this.token = (await post("start")).token;
+  // Starts a new game. The options, such as `{ width: 15, height: 9 }`, are
+  // all optional and the settings the game was started with are kept in
+  // `config`.
+  async start(options = {}) {
+    const { token, config } = await post("start", undefined, options);
+    this.token = token;
+    this.config = config;
+    if (this.socket) {
+      this.socket.close();
+    }
+    this.socket = offline ? undefined : new Socket(token);
+  }
+
+  async move(direction) {
+    if (this.socket) {
+      await this.socket.send({ act: { move: direction } });
+    } else {
+      await post(`move/${direction}`, this.token);
+    }
   }
 
   async moveUp() {
-    await post("move/up", this.token);
+    await this.move("up");
   }
 
   async moveDown() {
-    await post("move/down", this.token);
+    await this.move("down");
   }
 
   async moveLeft() {
-    await post("move/left", this.token);
+    await this.move("left");
   }
 
   async moveRight() {
-    await post("move/right", this.token);
+    await this.move("right");
+  }
+
+  // Every turn taken so far with the settings of the game, which the
+  // `ReplayPlayer` of the wasm build plays back.
+  async replay() {
+    return get("replay", this.token);
   }
 
   async updateMaze() {
-    parent.updateMaze(await get("map", this.token));
+    const map = this.socket
+      ? (await this.socket.send("observe")).observe
+      : await get("map", this.token);
+    parent.updateMaze(map);
   }
 }
@@ -12,10 +12,11 @@ async function run() {
 `;
 
 export const state = () => ({
-  maze: Array.from({ length: 9 }, () =>
-    Array.from({ length: 9 }, () => "hidden"),
-  ),
+  // Empty until a game is started, as the size of the maze depends on the
+  // options it was started with.
+  maze: [],
   script: DEFAULT_SCRIPT,
+  offline: false,
 });
 
 export const mutations = {
@@ -28,6 +29,9 @@ export const mutations = {
   resetScript(state) {
     state.script = DEFAULT_SCRIPT;
   },
+  toggleOffline(state) {
+    state.offline = !state.offline;
+  },
 };
 
 export const actions = {
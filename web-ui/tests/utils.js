@@ -3,11 +3,11 @@ import { createLocalVue } from "@vue/test-utils";
 import { mutations as consoleMutations } from "@/store/console";
 import { mutations } from "@/store";
 
-export const createStore = ({ script, lines }) => {
+export const createStore = ({ script, lines, offline = false }) => {
   const localVue = createLocalVue();
   localVue.use(Vuex);
   const store = new Vuex.Store({
-    state: { script },
+    state: { script, offline },
     mutations,
     modules: {
       console: {